@@ -0,0 +1,552 @@
+//! procedural macros for dabus, re-exported by it with the `macros` feature
+//!
+//! see [`macro@bus_stop`] and [`derive@Event`]
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    spanned::Spanned,
+    DeriveInput, Error, FnArg, GenericArgument, ImplItem, ImplItemFn, ItemImpl, LitStr, Path,
+    PathArguments, Result, ReturnType, Token, Type,
+};
+
+/// Implements `BusStop` for a type, registering every method marked with `#[handles(EVENT)]` as a handler for `EVENT`.
+///
+/// handlers must have the signature `async fn(&mut self, args: At, interface: BusInterface) -> Rt`,
+/// where `EVENT` is an `EventDef<_, At, Rt>`. this is checked at compile time, pointing at the mismatched type.
+/// handlers can also take `&self`, and in place of the interface any number of values implementing
+/// `dabus::event::extract::FromInterface`. the first parameter after `self` is always the event's arguments,
+/// so a handler taking only `self` can leave them out
+///
+/// `#[handles]` takes these options after the event:
+/// - `fallible`: register with `EventRegister::fallible_handler`
+/// - `forwarding`: register with `EventRegister::forwarding_handler`, the method returns `Result<Rt, CallTrace>`
+/// - `blocking`: register with `EventRegister::blocking_handler`, the method is a plain `fn(&mut self, args: At) -> Rt`
+/// - `shared`: register with `EventRegister::shared_handler`, the method takes `&self`
+/// - `declare`: declare the event (with `event!`) from the handler's signature, instead of using an existing one.
+///   the event has the same visibility as the method, and must be a plain name since it is declared in the
+///   current module
+///
+/// `Handles` is implemented for each event, so that stops can implement the marker traits declared by `events!`
+///
+/// ```rust
+/// use dabus::{bus_stop, event, BusInterface, bus::error::CallTrace};
+///
+/// event!(FLUSH_EVENT, (), ());
+///
+/// #[derive(Debug, Default)]
+/// struct Printer {
+///     buffer: String,
+/// }
+///
+/// #[bus_stop]
+/// impl Printer {
+///     #[handles(PRINT_EVENT, declare)]
+///     async fn print(&mut self, to_print: String, _i: BusInterface) {
+///         self.buffer.push_str(&to_print);
+///     }
+///
+///     #[handles(FLUSH_EVENT, forwarding)]
+///     async fn flush(&mut self, _: (), mut i: BusInterface) -> Result<(), CallTrace> {
+///         i.fire(PRINT_EVENT, String::from("\n")).await
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mut bus = dabus::DABus::new();
+/// bus.register(Printer::default());
+/// bus.fire(PRINT_EVENT, String::from("hello")).await.unwrap();
+/// # }
+/// ```
+///
+/// a mismatched signature fails to compile
+///
+/// ```compile_fail
+/// # use dabus::{bus_stop, event, BusInterface};
+/// event!(PRINT_EVENT, String, ());
+///
+/// #[derive(Debug)]
+/// struct Printer;
+///
+/// #[bus_stop]
+/// impl Printer {
+///     #[handles(PRINT_EVENT)]
+///     async fn print(&mut self, to_print: u32, _i: BusInterface) {}
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn bus_stop(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return Error::new(
+            TokenStream2::from(attr).span(),
+            "`#[bus_stop]` does not take any arguments",
+        )
+        .into_compile_error()
+        .into();
+    }
+    let item = parse_macro_input!(item as ItemImpl);
+    bus_stop_impl(item)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Marks a handler in a [`macro@bus_stop`] impl block
+#[proc_macro_attribute]
+pub fn handles(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut tokens = Error::new(
+        Span::call_site(),
+        "`#[handles]` can only be used on methods in a `#[bus_stop]` impl block",
+    )
+    .into_compile_error();
+    tokens.extend(TokenStream2::from(item));
+    tokens.into()
+}
+
+/// Implements `Event` for a type, making it an event identified by that type.
+///
+/// by default, the event's arguments are the type itself, it returns `()`, and it is named after the type.
+/// these can be changed with `#[event(args = Type, ret = Type, name = "name")]`
+///
+/// ```rust
+/// use dabus::{bus_stop, BusInterface, Event};
+///
+/// /// writes to the log
+/// #[derive(Debug, Event)]
+/// pub struct Log {
+///     pub line: String,
+/// }
+///
+/// /// counts the lines written so far
+/// #[derive(Event)]
+/// #[event(args = (), ret = usize)]
+/// pub struct LineCount;
+///
+/// #[derive(Debug, Default)]
+/// struct Logger {
+///     lines: Vec<String>,
+/// }
+///
+/// #[bus_stop]
+/// impl Logger {
+///     #[handles(Log::DEF)]
+///     async fn log(&mut self, log: Log, _i: BusInterface) {
+///         self.lines.push(log.line);
+///     }
+///
+///     #[handles(LineCount::DEF)]
+///     async fn count(&mut self, _: (), _i: BusInterface) -> usize {
+///         self.lines.len()
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mut bus = dabus::DABus::new();
+/// bus.register(Logger::default());
+/// bus.fire(Log::DEF, Log { line: String::from("hello") }).await.unwrap();
+/// assert_eq!(bus.fire(LineCount::DEF, ()).await.unwrap().ret(), 1);
+/// # }
+/// ```
+#[proc_macro_derive(Event, attributes(event))]
+pub fn derive_event(item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as DeriveInput);
+    derive_event_impl(item)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// the arguments of `#[event(...)]`
+#[derive(Default)]
+struct EventOptions {
+    args: Option<Type>,
+    ret: Option<Type>,
+    name: Option<LitStr>,
+}
+
+impl EventOptions {
+    fn parse_attr(&mut self, attr: &syn::Attribute) -> Result<()> {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("args") {
+                self.args = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("ret") {
+                self.ret = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("name") {
+                self.name = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected one of `args`, `ret`, or `name`"));
+            }
+            Ok(())
+        })
+    }
+}
+
+fn derive_event_impl(mut item: DeriveInput) -> Result<TokenStream2> {
+    let mut options = EventOptions::default();
+    for attr in &item.attrs {
+        if attr.path().is_ident("event") {
+            options.parse_attr(attr)?;
+        }
+    }
+    let ident = &item.ident;
+    let args = options.args.unwrap_or_else(|| syn::parse_quote!(Self));
+    let ret = options.ret.unwrap_or_else(|| syn::parse_quote!(()));
+    let name = options
+        .name
+        .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    // the event type must be 'static to be identified by its `TypeId`
+    for param in item.generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!('static));
+    }
+    // every instantiation of a generic event is named after its type parameters
+    let params = item
+        .generics
+        .type_params()
+        .map(|param| &param.ident)
+        .collect::<Vec<_>>();
+    let name_fn = (!params.is_empty()).then(|| {
+        quote! {
+            fn name() -> &'static str {
+                ::dabus::event::generic_name::<Self>(
+                    #name,
+                    &[#(::core::any::type_name::<#params>()),*],
+                )
+            }
+        }
+    });
+    let (impl_generics, ty_generics, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::dabus::Event for #ident #ty_generics #where_clause {
+            type Args = #args;
+            type Ret = #ret;
+            const NAME: &'static str = #name;
+            #name_fn
+        }
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum HandlerKind {
+    Plain,
+    Fallible,
+    Forwarding,
+    Blocking,
+    Shared,
+}
+
+/// the arguments of `#[handles(...)]`
+struct Handles {
+    event: Path,
+    kind: HandlerKind,
+    declare: bool,
+}
+
+impl Parse for Handles {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let event = input.parse::<Path>()?;
+        let mut kind = HandlerKind::Plain;
+        let mut declare = false;
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let option = input.parse::<syn::Ident>()?;
+            match option.to_string().as_str() {
+                "fallible" if kind == HandlerKind::Plain => kind = HandlerKind::Fallible,
+                "forwarding" if kind == HandlerKind::Plain => kind = HandlerKind::Forwarding,
+                "blocking" if kind == HandlerKind::Plain => kind = HandlerKind::Blocking,
+                "shared" if kind == HandlerKind::Plain => kind = HandlerKind::Shared,
+                "fallible" | "forwarding" | "blocking" | "shared" => {
+                    return Err(Error::new(
+                        option.span(),
+                        "a handler can only be one of `fallible`, `forwarding`, `blocking`, or `shared`",
+                    ))
+                }
+                "declare" => declare = true,
+                _ => {
+                    return Err(Error::new(
+                        option.span(),
+                        "expected one of `fallible`, `forwarding`, `blocking`, `shared`, or `declare`",
+                    ))
+                }
+            }
+        }
+        // `event!` declares the event in the current module, so it can only be given a name
+        if declare && event.get_ident().is_none() {
+            return Err(Error::new_spanned(
+                &event,
+                "declared events must be a plain name, such as `PRINT_EVENT`, as they are declared in this module",
+            ));
+        }
+        Ok(Self {
+            event,
+            kind,
+            declare,
+        })
+    }
+}
+
+/// a method marked with `#[handles]`
+struct Handler {
+    handles: Handles,
+    vis: syn::Visibility,
+    method: syn::Ident,
+    /// `None` if the method leaves out the arguments
+    args: Option<Type>,
+    /// the types of the parameters extracted from the interface
+    extracted: Vec<Type>,
+    ret: Type,
+}
+
+impl Handler {
+    /// the tag type of the event, which is either the type declared with it by `event!`, or the type of an `Event`
+    /// (when the event is given as `Type::DEF`)
+    fn event_tag(&self) -> Path {
+        let mut tag = self.handles.event.clone();
+        if tag.segments.len() > 1 && tag.segments.last().is_some_and(|last| last.ident == "DEF") {
+            tag.segments.pop();
+            tag.segments.pop_punct();
+        }
+        tag
+    }
+
+    /// the return type of the event (which is not the return type of the method for forwarding handlers)
+    fn event_ret(&self) -> Result<Type> {
+        if self.handles.kind != HandlerKind::Forwarding {
+            return Ok(self.ret.clone());
+        }
+        let error = || {
+            Error::new(
+                self.ret.span(),
+                "forwarding handlers must return `Result<Rt, CallTrace>`",
+            )
+        };
+        let Type::Path(path) = &self.ret else {
+            return Err(error());
+        };
+        let segment = path.path.segments.last().ok_or_else(error)?;
+        let PathArguments::AngleBracketed(args) = &segment.arguments else {
+            return Err(error());
+        };
+        match args.args.first() {
+            Some(GenericArgument::Type(ret)) if segment.ident == "Result" => Ok(ret.clone()),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// takes the `#[handles]` attribute off of a method, checking its signature if it had one
+fn take_handler(method: &mut ImplItemFn) -> Result<Option<Handler>> {
+    let Some(position) = method
+        .attrs
+        .iter()
+        .position(|attr| attr.path().is_ident("handles"))
+    else {
+        return Ok(None);
+    };
+    let handles = method.attrs.remove(position).parse_args::<Handles>()?;
+    if let Some(other) = method
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("handles"))
+    {
+        return Err(Error::new(
+            other.span(),
+            "a method can only handle one event, use a separate method for each",
+        ));
+    }
+
+    let sig = &method.sig;
+    let signature_error = |span| {
+        Error::new(
+            span,
+            "handlers must have the signature `async fn(&mut self, args: At, interface: BusInterface) -> Rt`, \
+             optionally with `&self` and extracted values in place of the interface",
+        )
+    };
+    if handles.kind == HandlerKind::Blocking {
+        return take_blocking_handler(method, handles).map(Some);
+    }
+    if sig.asyncness.is_none() {
+        return Err(signature_error(sig.fn_token.span));
+    }
+    let mut inputs = sig.inputs.iter();
+    let Some(FnArg::Receiver(receiver)) = inputs.next() else {
+        return Err(signature_error(sig.inputs.span()));
+    };
+    if receiver.reference.is_none() {
+        return Err(signature_error(receiver.span()));
+    }
+    if handles.kind == HandlerKind::Shared && receiver.mutability.is_some() {
+        return Err(Error::new_spanned(
+            receiver,
+            "shared handlers must take `&self`",
+        ));
+    }
+    let mut typed = vec![];
+    for input in inputs {
+        let FnArg::Typed(input) = input else {
+            return Err(signature_error(input.span()));
+        };
+        typed.push((*input.ty).clone());
+    }
+    let mut typed = typed.into_iter();
+    let args = typed.next();
+    let extracted = typed.collect::<Vec<_>>();
+    if extracted.len() > 4 {
+        return Err(Error::new(
+            extracted[4].span(),
+            "handlers can take at most four extracted values",
+        ));
+    }
+    let ret = match &sig.output {
+        ReturnType::Default => syn::parse_quote!(()),
+        ReturnType::Type(_, ret) => (**ret).clone(),
+    };
+    Ok(Some(Handler {
+        handles,
+        vis: method.vis.clone(),
+        method: sig.ident.clone(),
+        args,
+        extracted,
+        ret,
+    }))
+}
+
+/// checks the signature of a blocking handler, which can not extract values or leave out its arguments
+fn take_blocking_handler(method: &ImplItemFn, handles: Handles) -> Result<Handler> {
+    let sig = &method.sig;
+    let signature_error = |span| {
+        Error::new(
+            span,
+            "blocking handlers must have the signature `fn(&mut self, args: At) -> Rt`",
+        )
+    };
+    if let Some(asyncness) = sig.asyncness {
+        return Err(signature_error(asyncness.span));
+    }
+    let mut inputs = sig.inputs.iter();
+    match inputs.next() {
+        Some(FnArg::Receiver(receiver))
+            if receiver.reference.is_some() && receiver.mutability.is_some() => {}
+        _ => return Err(signature_error(sig.inputs.span())),
+    }
+    let (Some(FnArg::Typed(args)), None) = (inputs.next(), inputs.next()) else {
+        return Err(signature_error(sig.inputs.span()));
+    };
+    let ret = match &sig.output {
+        ReturnType::Default => syn::parse_quote!(()),
+        ReturnType::Type(_, ret) => (**ret).clone(),
+    };
+    Ok(Handler {
+        handles,
+        vis: method.vis.clone(),
+        method: sig.ident.clone(),
+        args: Some((*args.ty).clone()),
+        extracted: vec![],
+        ret,
+    })
+}
+
+fn bus_stop_impl(mut item: ItemImpl) -> Result<TokenStream2> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(Error::new(
+            path.span(),
+            "`#[bus_stop]` must be used on an inherent impl block",
+        ));
+    }
+    // errors are collected, so that every bad handler is reported at once and the methods are still emitted
+    let mut errors: Option<Error> = None;
+    let mut push_error = |error: Error| match &mut errors {
+        Some(errors) => errors.combine(error),
+        None => errors = Some(error),
+    };
+    let mut handlers = vec![];
+    for impl_item in &mut item.items {
+        if let ImplItem::Fn(method) = impl_item {
+            match take_handler(method) {
+                Ok(handler) => handlers.extend(handler),
+                Err(error) => push_error(error),
+            }
+        }
+    }
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    let mut declarations = TokenStream2::new();
+    let mut markers = TokenStream2::new();
+    let mut checks = TokenStream2::new();
+    let mut registrations = TokenStream2::new();
+    for handler in &handlers {
+        let event = &handler.handles.event;
+        let method = &handler.method;
+        let args = handler
+            .args
+            .clone()
+            .unwrap_or_else(|| syn::parse_quote!(()));
+        let ret = match handler.event_ret() {
+            Ok(ret) => ret,
+            Err(error) => {
+                push_error(error);
+                continue;
+            }
+        };
+        if handler.handles.declare {
+            let vis = &handler.vis;
+            declarations.extend(quote! {
+                ::dabus::event!(#vis #event, #args, #ret);
+            });
+        }
+        let tag = handler.event_tag();
+        markers.extend(quote! {
+            impl #impl_generics ::dabus::event::Handles<#tag> for #self_ty #where_clause {}
+        });
+        // each check is spanned to the type it checks, so that mismatches point at the handler's signature
+        if let Some(args) = &handler.args {
+            checks.extend(quote_spanned! {args.span()=>
+                let _: &'static ::dabus::EventDef<_, #args, _> = #event;
+            });
+        }
+        checks.extend(quote_spanned! {ret.span()=>
+            let _: &'static ::dabus::EventDef<_, _, #ret> = #event;
+        });
+        for extracted in &handler.extracted {
+            checks.extend(quote_spanned! {extracted.span()=>
+                let _ = <#extracted as ::dabus::event::extract::FromInterface>::from_interface;
+            });
+        }
+        let register = match handler.handles.kind {
+            HandlerKind::Plain => quote!(handler),
+            HandlerKind::Fallible => quote!(fallible_handler),
+            HandlerKind::Forwarding => quote!(forwarding_handler),
+            HandlerKind::Blocking => quote!(blocking_handler),
+            HandlerKind::Shared => quote!(shared_handler),
+        };
+        registrations.extend(quote! {
+            .#register(#event, Self::#method)
+        });
+    }
+
+    let mut output = declarations;
+    item.to_tokens(&mut output);
+    if let Some(errors) = errors {
+        output.extend(errors.into_compile_error());
+        return Ok(output);
+    }
+    output.extend(markers);
+    output.extend(quote! {
+        impl #impl_generics ::dabus::BusStop for #self_ty #where_clause {
+            fn registered_handlers(
+                h: ::dabus::EventRegister<Self>,
+            ) -> ::dabus::EventRegister<Self> {
+                #checks
+                h #registrations
+            }
+        }
+    });
+    Ok(output)
+}
@@ -0,0 +1,14 @@
+use dabus::{bus_stop, event};
+
+event!(GREET, String, ());
+
+#[derive(Debug)]
+struct Greeter;
+
+#[bus_stop]
+impl Greeter {
+    #[handles(GREET, blocking)]
+    async fn greet(&mut self, _name: String) {}
+}
+
+fn main() {}
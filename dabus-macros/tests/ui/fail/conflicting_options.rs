@@ -0,0 +1,14 @@
+use dabus::{bus_stop, event, BusInterface};
+
+event!(GREET, String, ());
+
+#[derive(Debug)]
+struct Greeter;
+
+#[bus_stop]
+impl Greeter {
+    #[handles(GREET, shared, blocking)]
+    async fn greet(&self, _name: String, _i: BusInterface) {}
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use dabus::{bus_stop, BusInterface};
+
+mod events {}
+
+#[derive(Debug)]
+struct Greeter;
+
+#[bus_stop]
+impl Greeter {
+    #[handles(events::GREET, declare)]
+    async fn greet(&mut self, _name: String, _i: BusInterface) {}
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+use dabus::{bus_stop, event, BusInterface};
+
+event!(GREET, String, u32);
+
+#[derive(Debug)]
+struct Greeter;
+
+#[bus_stop]
+impl Greeter {
+    #[handles(GREET, forwarding)]
+    async fn greet(&mut self, _name: String, _i: BusInterface) -> u32 {
+        0
+    }
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use dabus::{event, handles, BusInterface};
+
+event!(GREET, String, ());
+
+#[derive(Debug)]
+struct Greeter;
+
+impl Greeter {
+    #[handles(GREET)]
+    async fn greet(&mut self, _name: String, _i: BusInterface) {}
+}
+
+fn main() {}
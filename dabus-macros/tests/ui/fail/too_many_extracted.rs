@@ -0,0 +1,29 @@
+use dabus::{
+    bus::export::{SpanContext, TraceId},
+    bus::error::CallEvent,
+    bus_stop, event,
+    event::extract::Deadline,
+    BusInterface,
+};
+
+event!(GREET, String, ());
+
+#[derive(Debug)]
+struct Greeter;
+
+#[bus_stop]
+impl Greeter {
+    #[handles(GREET)]
+    async fn greet(
+        &mut self,
+        _name: String,
+        _deadline: Deadline,
+        _trace_id: TraceId,
+        _span: SpanContext,
+        _event: CallEvent,
+        _i: BusInterface,
+    ) {
+    }
+}
+
+fn main() {}
@@ -0,0 +1,99 @@
+use dabus::{
+    bus::{error::CallTrace, export::TraceId},
+    bus_stop, event,
+    event::extract::{Deadline, Resource},
+    BusInterface, DABus,
+};
+
+#[derive(Debug)]
+struct NotANumber;
+
+impl std::fmt::Display for NotANumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("not a number")
+    }
+}
+
+impl std::error::Error for NotANumber {}
+
+event!(PARSE, String, Result<u32, NotANumber>);
+event!(SUM, Vec<u32>, u32);
+event!(COUNT, (), usize);
+event!(PARSE_ALL, Vec<String>, u32);
+event!(EXTRACT, (), bool);
+
+struct Offset {
+    value: u32,
+}
+
+#[derive(Debug, Default)]
+struct Numbers {
+    parsed: usize,
+}
+
+#[bus_stop]
+impl Numbers {
+    #[handles(PARSE, fallible)]
+    async fn parse(&mut self, text: String, _i: BusInterface) -> Result<u32, NotANumber> {
+        self.parsed += 1;
+        text.parse().map_err(|_| NotANumber)
+    }
+
+    #[handles(COUNT, shared)]
+    async fn count(&self) -> usize {
+        self.parsed
+    }
+
+    #[handles(DOUBLE, declare)]
+    pub(crate) async fn double(&mut self, n: u32) -> u32 {
+        n * 2
+    }
+
+    #[handles(EXTRACT)]
+    async fn extract(
+        &mut self,
+        (): (),
+        offset: Resource<Offset>,
+        deadline: Deadline,
+        _trace_id: TraceId,
+        _i: BusInterface,
+    ) -> bool {
+        offset.value == 1 && deadline.0.is_none()
+    }
+}
+
+#[derive(Debug)]
+struct Summer;
+
+#[bus_stop]
+impl Summer {
+    #[handles(SUM, blocking)]
+    fn sum(&mut self, numbers: Vec<u32>) -> u32 {
+        numbers.iter().sum()
+    }
+
+    #[handles(PARSE_ALL, forwarding)]
+    async fn parse_all(&mut self, texts: Vec<String>, mut i: BusInterface) -> Result<u32, CallTrace> {
+        let mut total = 0;
+        for text in texts {
+            total += i.fire(PARSE, text).await?.unwrap_or(0);
+        }
+        Ok(total)
+    }
+}
+
+fn main() {
+    let mut bus = DABus::new();
+    bus.set_resource(Offset { value: 1 });
+    bus.register(Numbers::default());
+    bus.register(Summer);
+
+    assert_eq!(bus.fire_blocking(PARSE, String::from("4")).unwrap().ret().unwrap(), 4);
+    assert!(bus.fire_blocking(PARSE, String::from("four")).unwrap().ret().is_err());
+    assert_eq!(bus.fire_blocking(COUNT, ()).unwrap().ret(), 2);
+    assert_eq!(bus.fire_blocking(DOUBLE, 4).unwrap().ret(), 8);
+    assert!(bus.fire_blocking(EXTRACT, ()).unwrap().ret());
+    assert_eq!(bus.fire_blocking(SUM, vec![1, 2, 3]).unwrap().ret(), 6);
+    let texts = vec![String::from("1"), String::from("x"), String::from("2")];
+    assert_eq!(bus.fire_blocking(PARSE_ALL, texts).unwrap().ret(), 3);
+}
@@ -0,0 +1,34 @@
+use dabus::{bus_stop, BusInterface, DABus, Event};
+
+mod events {
+    dabus::event!(pub PRINT, String, ());
+}
+
+#[derive(Event)]
+#[event(args = (), ret = usize)]
+struct Printed;
+
+#[derive(Debug, Default)]
+struct Printer {
+    lines: Vec<String>,
+}
+
+#[bus_stop]
+impl Printer {
+    #[handles(events::PRINT)]
+    async fn print(&mut self, line: String, _i: BusInterface) {
+        self.lines.push(line);
+    }
+
+    #[handles(Printed::DEF, shared)]
+    async fn printed(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+fn main() {
+    let mut bus = DABus::new();
+    bus.register(Printer::default());
+    bus.fire_blocking(events::PRINT, String::from("hello")).unwrap();
+    assert_eq!(bus.fire_blocking(Printed::DEF, ()).unwrap().ret(), 1);
+}
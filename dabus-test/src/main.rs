@@ -11,7 +11,9 @@ use std::io::Write;
 
 use anyhow::Result;
 
-use dabus::{BusErrorUtil as _, BusInterface, BusStop, DABus, EventRegister};
+use dabus::{
+    bus::error::CallTrace, bus_stop, BusErrorUtil as _, BusInterface, BusStop, DABus, EventRegister,
+};
 
 async fn asmain() -> Result<()> {
     pretty_env_logger::formatted_builder()
@@ -59,18 +61,16 @@ event!(HELLO_EVENT, (), ());
 pub struct HelloHandler;
 
 impl HelloHandler {
-    async fn hello_world(&mut self, _: (), mut i: BusInterface) {
-        i.fire(PRINT_EVENT, "Hello, World!".to_string())
-            .await
-            .unwrap_or_fwd(&i)
-            .await;
-        i.fire(FLUSH_EVENT, ()).await.unwrap_or_fwd(&i).await;
+    async fn hello_world(&mut self, _: (), mut i: BusInterface) -> Result<(), CallTrace> {
+        i.fire(PRINT_EVENT, "Hello, World!".to_string()).await?;
+        i.fire(FLUSH_EVENT, ()).await?;
+        Ok(())
     }
 }
 
 impl BusStop for HelloHandler {
     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
-        h.handler(HELLO_EVENT, Self::hello_world)
+        h.forwarding_handler(HELLO_EVENT, Self::hello_world)
     }
 }
 
@@ -91,10 +91,7 @@ impl BusStop for STDOut {
     }
 }
 
-event!(PRINT_EVENT, String, ());
-event!(FLUSH_EVENT, (), ());
-
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Printer {
     buffer: String,
 }
@@ -106,11 +103,16 @@ impl Printer {
             buffer: String::new(),
         }
     }
+}
 
+#[bus_stop]
+impl Printer {
+    #[handles(PRINT_EVENT, declare)]
     async fn print(&mut self, to_print: String, _i: BusInterface) {
         self.buffer = format!("{}\n{}", self.buffer, to_print);
     }
 
+    #[handles(FLUSH_EVENT, declare)]
     async fn flush(&mut self, _: (), mut i: BusInterface) {
         self.buffer.push('\n');
         i.fire(WRITE_EVENT, self.buffer.clone())
@@ -121,10 +123,3 @@ impl Printer {
         self.buffer.clear();
     }
 }
-
-impl BusStop for Printer {
-    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
-        h.handler(PRINT_EVENT, Self::print)
-            .handler(FLUSH_EVENT, Self::flush)
-    }
-}
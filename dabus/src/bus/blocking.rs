@@ -0,0 +1,118 @@
+//! running blocking handlers without stalling the executor
+//!
+//! handlers registered with [`EventRegister::blocking_handler`] are plain functions, which are run through the
+//! [`Spawner`] set with [`DABus::set_spawner`] (a small pool of threads, by default). while one runs, its stop
+//! is moved to the blocking thread and is busy like it would be for an async handler
+//!
+//! ```rust
+//! use dabus::{event, BusStop, DABus, EventRegister};
+//!
+//! event!(HASH, Vec<u8>, u64);
+//!
+//! #[derive(Debug)]
+//! struct Hasher;
+//!
+//! impl Hasher {
+//!     fn hash(&mut self, data: Vec<u8>) -> u64 {
+//!         // pretend this takes a while
+//!         data.iter().fold(0, |hash, byte| hash.wrapping_mul(31).wrapping_add(u64::from(*byte)))
+//!     }
+//! }
+//!
+//! impl BusStop for Hasher {
+//!     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+//!         h.blocking_handler(HASH, Self::hash)
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut bus = DABus::new();
+//! bus.set_spawner(|task| drop(tokio::task::spawn_blocking(task)));
+//! bus.register(Hasher);
+//! assert_eq!(bus.fire(HASH, vec![1, 2]).await.unwrap().ret(), 33);
+//! # }
+//! ```
+//!
+//! [`EventRegister::blocking_handler`]: crate::EventRegister::blocking_handler
+//! [`DABus::set_spawner`]: crate::DABus::set_spawner
+
+use std::{
+    num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
+    sync::OnceLock,
+    thread,
+};
+
+use flume::{SendError, Sender};
+
+/// a call to a blocking handler, which must be run to completion (panics included) by a [`Spawner`]
+pub type BlockingTask = Box<dyn FnOnce() + Send + 'static>;
+
+/// Runs calls to blocking handlers somewhere they can block, see [`DABus::set_spawner`]
+///
+/// implemented for closures, such as `|task| drop(tokio::task::spawn_blocking(task))`. a spawner that drops a task
+/// without running it makes the call panic
+///
+/// [`DABus::set_spawner`]: crate::DABus::set_spawner
+pub trait Spawner {
+    fn spawn_blocking(&self, task: BlockingTask);
+}
+
+impl<F> Spawner for F
+where
+    F: Fn(BlockingTask),
+{
+    fn spawn_blocking(&self, task: BlockingTask) {
+        self(task);
+    }
+}
+
+/// The default [`Spawner`], which works without any executor by running calls on a pool of threads shared by every
+/// bus
+///
+/// the pool has one thread per available core (at least [`ThreadSpawner::MIN_THREADS`]), started on first use. calls
+/// made while every thread is busy wait for one to be free, so handlers that block for a long time (on io, for
+/// example) are better run through a spawner without a limit, such as tokio's blocking pool
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadSpawner;
+
+impl ThreadSpawner {
+    /// the smallest number of threads in the pool
+    pub const MIN_THREADS: usize = 4;
+}
+
+impl Spawner for ThreadSpawner {
+    fn spawn_blocking(&self, task: BlockingTask) {
+        if let Err(SendError(task)) = pool().send(task) {
+            // no thread of the pool could be started
+            std::thread::spawn(task);
+        }
+    }
+}
+
+/// the threads used by [`ThreadSpawner`]
+fn pool() -> &'static Sender<BlockingTask> {
+    static POOL: OnceLock<Sender<BlockingTask>> = OnceLock::new();
+    POOL.get_or_init(|| {
+        let (tasks, recv) = flume::unbounded();
+        let threads = thread::available_parallelism()
+            .map_or(0, NonZeroUsize::get)
+            .max(ThreadSpawner::MIN_THREADS);
+        for n in 0..threads {
+            let recv = recv.clone();
+            if let Err(err) = thread::Builder::new()
+                .name(format!("dabus-blocking-{n}"))
+                .spawn(move || {
+                    for task in recv.iter() {
+                        // the bus catches panics in handlers, this keeps the thread alive if a task panics anyway
+                        let _ = panic::catch_unwind(AssertUnwindSafe(task));
+                    }
+                })
+            {
+                error!("Could not start a blocking thread: {}", err);
+            }
+        }
+        tasks
+    })
+}
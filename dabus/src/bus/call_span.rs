@@ -0,0 +1,79 @@
+//! per-call spans for the `tracing` feature
+//!
+//! when the feature is disabled, [`CallSpan`] is a zero sized stand-in with no-op methods,
+//! so the runtime does not have to care about which one it is using
+
+use futures::future::BoxFuture;
+#[cfg(feature = "tracing")]
+use tracing::Instrument;
+
+use crate::bus::error::{CallEvent, Resolution};
+
+/// the span of a single (top level or nested) call on the bus
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone)]
+pub(crate) struct CallSpan(tracing::Span);
+
+/// the span of a single (top level or nested) call on the bus
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone)]
+pub(crate) struct CallSpan;
+
+#[cfg(feature = "tracing")]
+impl CallSpan {
+    /// opens a span for the call described by `event`.
+    ///
+    /// nested calls pass the span of the calling handler as `parent`, top level calls use
+    /// whatever span is current when the bus is polled
+    pub fn new(parent: Option<&Self>, event: &CallEvent, stop: Option<&'static str>) -> Self {
+        let stop = stop.unwrap_or("<none>");
+        let span = match parent {
+            Some(parent) => tracing::info_span!(
+                parent: &parent.0,
+                "call",
+                event = event.handler_name,
+                stop = stop,
+                args = event.handler_args_t,
+                ret = event.return_t,
+                resolution = tracing::field::Empty,
+            ),
+            None => tracing::info_span!(
+                "call",
+                event = event.handler_name,
+                stop = stop,
+                args = event.handler_args_t,
+                ret = event.return_t,
+                resolution = tracing::field::Empty,
+            ),
+        };
+        Self(span)
+    }
+
+    pub fn record_resolution(&self, resolution: &Resolution) {
+        self.0
+            .record("resolution", tracing::field::debug(resolution));
+    }
+
+    /// attaches the span to a handler future, so that events emitted from inside the handler are attributed to this call
+    pub fn instrument<T: 'static>(&self, fut: BoxFuture<'static, T>) -> BoxFuture<'static, T> {
+        Box::pin(fut.instrument(self.0.clone()))
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl CallSpan {
+    #[inline(always)]
+    pub fn new(_: Option<&Self>, _: &CallEvent, _: Option<&'static str>) -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    #[allow(clippy::unused_self)]
+    pub fn record_resolution(&self, _: &Resolution) {}
+
+    #[inline(always)]
+    #[allow(clippy::unused_self)]
+    pub fn instrument<T: 'static>(&self, fut: BoxFuture<'static, T>) -> BoxFuture<'static, T> {
+        fut
+    }
+}
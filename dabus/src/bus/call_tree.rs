@@ -0,0 +1,186 @@
+//! the handlers running as part of a top level call
+//!
+//! every running handler is a [`Frame`], linked to the frame of the handler that called it. all frames are
+//! polled together, so a handler can make several nested calls at once. nested calls to a stop that is busy
+//! with other frames are queued until it is free, unless one of them is waiting on the caller (which would never finish).
+//! a stop is not busy for calls to its shared handlers while only shared handlers are using it, and no call to one of
+//! its other handlers is queued
+
+use core::any::TypeId;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use flume::{r#async::RecvFut, Receiver};
+use futures::{future::BoxFuture, FutureExt};
+
+use crate::{
+    bus::{
+        call_span::CallSpan,
+        error::CallEvent,
+        metrics::InFlight,
+        policy::{CloneArgs, RetryPolicy},
+    },
+    core::dyn_var::DynVar,
+    event::async_fn_ptr::HandlerOutput,
+    interface::{BusInterfaceEvent, Responder},
+    stop::BusStopContainer,
+};
+
+pub(crate) type FrameId = usize;
+
+/// a running handler
+pub(crate) struct Frame {
+    /// the frame that made this call, `None` for the top level call
+    pub parent: Option<FrameId>,
+    /// how many calls deep this is, starting at 0 for the top level call
+    pub depth: usize,
+    /// keeps the frame's [`BusInterface`] alive, see [`BusInterface::is_expired`].
+    /// this is dropped before the receiver, so that callers never see the channel close before the interface expires
+    ///
+    /// [`BusInterface`]: crate::BusInterface
+    /// [`BusInterface::is_expired`]: crate::BusInterface::is_expired
+    pub liveness: Arc<()>,
+    pub interface_recv: Receiver<BusInterfaceEvent>,
+    /// `None` once every copy of the interface has been dropped
+    pub recev_fut: Option<RecvFut<'static, BusInterfaceEvent>>,
+    pub handler: Arc<BusStopContainer>,
+    /// the handler is a shared handler, which only borrows its stop
+    pub shared: bool,
+    pub handler_fut: BoxFuture<'static, HandlerOutput>,
+    /// the event that was called (even if it is being handled by the dead letter handler)
+    pub def: TypeId,
+    /// the handler is the dead letter handler, and returns an `Option` of the real return value
+    pub dead_letter: bool,
+    /// `None` if the call will not be retried
+    pub retry: Option<Box<Retry>>,
+    /// where the result of the call is sent, `None` for the top level call
+    pub responder: Option<Responder>,
+    pub local_trace_data: CallEvent,
+    pub span: CallSpan,
+    pub started: Instant,
+    /// counts the call as in flight in the bus metrics, even if the frame is dropped without finishing
+    pub in_flight: InFlight,
+}
+
+/// what is needed to retry a call, see [`RetryPolicy`]
+pub(crate) struct Retry {
+    pub policy: RetryPolicy,
+    /// a copy of the arguments, for the next attempt
+    pub args: DynVar,
+    pub clone_args: CloneArgs,
+    /// the trace of the call, before it was first started
+    pub template: CallEvent,
+    /// the traces of the attempts that failed
+    pub previous: Vec<CallEvent>,
+}
+
+/// a nested call waiting for its stop to be free
+pub(crate) struct QueuedCall {
+    pub caller: FrameId,
+    pub def: TypeId,
+    pub args: DynVar,
+    pub responder: Responder,
+    pub trace_data: CallEvent,
+    /// the call is waiting on a rate limit, and should not be run before this
+    pub not_before: Option<Instant>,
+}
+
+/// something that happened to a frame, and needs handling by the runtime
+#[allow(clippy::large_enum_variant)] // handled right away
+pub(crate) enum FrameEvent {
+    Interface(FrameId, BusInterfaceEvent),
+    Returned(FrameId, HandlerOutput),
+    /// a queued call may be ready to run
+    Wakeup,
+}
+
+#[derive(Default)]
+pub(crate) struct CallTree {
+    pub frames: BTreeMap<FrameId, Frame>,
+    pub queued: Vec<QueuedCall>,
+    /// timers for queued calls that are waiting on a rate limit, along with when they should end
+    pub wakeups: Vec<(Instant, BoxFuture<'static, ()>)>,
+    /// the number of nested calls that have been started
+    pub nested_calls: usize,
+    next_id: FrameId,
+}
+
+impl CallTree {
+    pub fn insert(&mut self, frame: Frame) -> FrameId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.frames.insert(id, frame);
+        id
+    }
+
+    /// polls every frame, returning the first thing that needs handling
+    pub fn poll(&mut self, cx: &mut Context<'_>) -> Poll<FrameEvent> {
+        for (id, frame) in &mut self.frames {
+            if let Some(recev_fut) = &mut frame.recev_fut {
+                match recev_fut.poll_unpin(cx) {
+                    Poll::Ready(Ok(event)) => {
+                        frame.recev_fut = Some(frame.interface_recv.clone().into_recv_async());
+                        return Poll::Ready(FrameEvent::Interface(*id, event));
+                    }
+                    Poll::Ready(Err(..)) => {
+                        // no more nested calls can be made, so only the handler is left to wait for
+                        debug!("Handler dropped its interface");
+                        frame.recev_fut = None;
+                    }
+                    Poll::Pending => {}
+                }
+            }
+            if let Poll::Ready(output) = frame.handler_fut.poll_unpin(cx) {
+                return Poll::Ready(FrameEvent::Returned(*id, output));
+            }
+        }
+        let waiting = self.wakeups.len();
+        self.wakeups
+            .retain_mut(|(_, wakeup)| wakeup.poll_unpin(cx).is_pending());
+        if self.wakeups.len() < waiting {
+            return Poll::Ready(FrameEvent::Wakeup);
+        }
+        Poll::Pending
+    }
+
+    /// the frames using the stop that handles `def`, none if it is not busy
+    pub fn holders(&self, def: TypeId) -> impl Iterator<Item = FrameId> + '_ {
+        self.frames
+            .iter()
+            .filter(move |(_, frame)| frame.handler.relevant(def))
+            .map(|(id, _)| *id)
+    }
+
+    /// checks if `from` can not finish before `to` does, because it is (possibly indirectly) waiting on it
+    ///
+    /// handlers are assumed to wait on all of their nested calls
+    pub fn waits_on(&self, from: FrameId, to: FrameId) -> bool {
+        let mut seen = BTreeSet::new();
+        let mut next = vec![from];
+        while let Some(id) = next.pop() {
+            if id == to {
+                return true;
+            }
+            if !seen.insert(id) {
+                continue;
+            }
+            next.extend(
+                self.frames
+                    .iter()
+                    .filter(|(_, frame)| frame.parent == Some(id))
+                    .map(|(child, _)| *child),
+            );
+            next.extend(
+                self.queued
+                    .iter()
+                    .filter(|call| call.caller == id)
+                    .flat_map(|call| self.holders(call.def)),
+            );
+        }
+        false
+    }
+}
@@ -0,0 +1,155 @@
+//! structured capture of handler arguments and return values (`backtrace_serde` feature)
+//!
+//! values whose type implements [`Serialize`] are recorded in [`CallEvent::args_value`] and
+//! [`CallEvent::return_value`] as JSON, so traces (which are themselves [`Serialize`]) can be stored,
+//! queried and diffed by machine. values are capped in size (see [`DABus::set_capture_limit`]), and
+//! fields can be kept out of traces with [`Redacted`] or [`redact`].
+//!
+//! finding out if a type implements [`Serialize`] needs the `nightly` feature. without it, only the types
+//! registered with [`DABus::capture`] are recorded
+//!
+//! [`CallEvent::args_value`]: crate::bus::error::CallEvent::args_value
+//! [`CallEvent::return_value`]: crate::bus::error::CallEvent::return_value
+//! [`DABus::set_capture_limit`]: crate::DABus::set_capture_limit
+//! [`DABus::capture`]: crate::DABus::capture
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    io,
+    ops::{Deref, DerefMut},
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::{core::dyn_var::DynVar, util::dyn_debug::DynDebug};
+
+/// the default value of [`DABus::set_capture_limit`]
+///
+/// [`DABus::set_capture_limit`]: crate::DABus::set_capture_limit
+pub const DEFAULT_CAPTURE_LIMIT: usize = 4096;
+
+/// what is placed in traces instead of redacted values
+pub const REDACTED: &str = "<redacted>";
+
+/// a captured argument or return value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum Captured {
+    /// the value, as JSON
+    Value(serde_json::Value),
+    /// the serialized value was larger than the capture limit (contained here), and was dropped
+    Truncated(usize),
+    /// the type does not implement [`Serialize`], or failed to serialize
+    Unserializable,
+}
+
+/// serializes a value of a type registered with [`DABus::capture`](crate::DABus::capture)
+pub(crate) type SerializeFn = fn(&DynVar, &mut dyn io::Write) -> Option<serde_json::Result<()>>;
+
+pub(crate) fn serialize_as<T: Serialize + DynDebug + 'static>(
+    value: &DynVar,
+    writer: &mut dyn io::Write,
+) -> Option<serde_json::Result<()>> {
+    Some(serde_json::to_writer(writer, value.as_ref::<T>()?))
+}
+
+impl Captured {
+    /// captures `value`, with `serialize` if its type was registered
+    pub(crate) fn capture(value: &DynVar, limit: usize, serialize: Option<&SerializeFn>) -> Self {
+        let mut writer = LimitedWriter { buf: vec![], limit };
+        let serialized = match serialize {
+            Some(serialize) => serialize(value, &mut writer),
+            None => value.serialize_json(&mut writer),
+        };
+        match serialized {
+            Some(Ok(())) => {
+                serde_json::from_slice(&writer.buf).map_or(Self::Unserializable, Self::Value)
+            }
+            Some(Err(..)) if writer.buf.len() >= limit => Self::Truncated(limit),
+            Some(Err(..)) | None => Self::Unserializable,
+        }
+    }
+}
+
+/// stops serialization once `limit` bytes have been written
+struct LimitedWriter {
+    buf: Vec<u8>,
+    limit: usize,
+}
+
+impl io::Write for LimitedWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.buf.len() + data.len() > self.limit {
+            self.buf.resize(self.limit, 0);
+            return Err(io::Error::other("capture limit exceeded"));
+        }
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Wrapper for values that should never show up in traces
+///
+/// it serializes and debug-formats as `"<redacted>"`, and otherwise behaves like the value it contains
+///
+/// ```rust
+/// use dabus::bus::capture::Redacted;
+///
+/// #[derive(Debug, serde::Serialize)]
+/// struct Login {
+///     user: String,
+///     password: Redacted<String>,
+/// }
+/// ```
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Redacted<T>(pub T);
+
+impl<T> Redacted<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Redacted<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> Deref for Redacted<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Redacted<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> Debug for Redacted<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> Serialize for Redacted<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+/// for use with `#[serde(serialize_with = "dabus::bus::capture::redact")]`, to redact a field without changing its type
+///
+/// # Errors
+///
+/// if the serializer fails to serialize a string
+pub fn redact<T, S: Serializer>(_: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(REDACTED)
+}
@@ -1,14 +1,36 @@
-use std::{any::type_name, fmt::Write};
+use std::{
+    any::type_name,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    time::SystemTime,
+};
 
-use crate::{core::dyn_var::DynVar, unique_type, util::dyn_debug::DynDebug, EventDef};
+#[cfg(feature = "backtrace_serde")]
+use crate::bus::capture::Captured;
+use crate::{
+    bus::{export::SpanContext, render::TraceDisplay},
+    core::dyn_var::DynVar,
+    unique_type,
+    util::dyn_debug::DynDebug,
+    EventDef,
+};
 
 #[derive(Clone, Debug, thiserror::Error)]
+#[cfg_attr(feature = "backtrace_serde", derive(serde::Serialize))]
 #[allow(clippy::module_name_repetitions)]
 #[error("Failed to execute event!\n{err:?}")]
 pub struct FireEventError {
     err: BaseFireEventError,
 }
 
+impl FireEventError {
+    /// the underlying error
+    #[must_use]
+    pub const fn base(&self) -> &BaseFireEventError {
+        &self.err
+    }
+}
+
 impl From<BaseFireEventError> for FireEventError {
     fn from(err: BaseFireEventError) -> Self {
         Self { err }
@@ -16,14 +38,119 @@ impl From<BaseFireEventError> for FireEventError {
 }
 
 #[derive(Clone, Debug, thiserror::Error)]
+#[cfg_attr(feature = "backtrace_serde", derive(serde::Serialize))]
 #[allow(clippy::module_name_repetitions)]
 pub enum BaseFireEventError {
     #[error("No handler matches the event!")]
     NoHandler,
+    /// the value returned for an event was not of the event's return type
+    #[error("Expected a value of type {expected}, but found {found}!")]
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// the stop that handles the event is already handling another event further up the call stack
+    #[error(
+        "The stop handling the event is busy (was the event fired from one of its own handlers?)"
+    )]
+    HandlerBusy,
+    /// the [`BusInterface`](crate::BusInterface) used to fire the event is no longer connected to the runtime
+    #[error("The bus interface is no longer connected to the runtime!")]
+    InterfaceClosed,
+    /// the [`BusInterface`](crate::BusInterface) used to fire the event was used after its handler returned
+    #[error("The bus interface was used after its handler returned!")]
+    InterfaceExpired,
+    /// the call would be nested more deeply than allowed by [`DABus::set_max_depth`](crate::DABus::set_max_depth)
+    #[error("Nested calls went deeper than the limit of {limit}!")]
+    DepthExceeded { limit: usize },
+    /// the top level call has already made as many nested calls as allowed by [`DABus::set_call_budget`](crate::DABus::set_call_budget)
+    #[error("The top level call made more than the limit of {limit} nested calls!")]
+    BudgetExceeded { limit: usize },
+    /// strict mode is enabled, and some required events have no handler (see [`DABus::set_strict`](crate::DABus::set_strict))
+    #[error("{0}")]
+    MissingHandlers(MissingHandlers),
+    /// the event has failed too many times in a row, and is not being run (see [`CircuitBreaker`](crate::bus::policy::CircuitBreaker))
+    #[error("The circuit breaker for the event is open!")]
+    CircuitOpen,
+    /// the event is over its [`RateLimit`](crate::bus::policy::RateLimit)
+    #[error("The event is over its rate limit!")]
+    RateLimited,
+    /// the call was not started before its deadline (see [`BusInterface::deadline`](crate::BusInterface::deadline))
+    #[error("The call was not started before its deadline!")]
+    DeadlineExceeded,
+    /// a handler asked for a [`Resource`](crate::event::extract::Resource) that was never set
+    #[error("No resource of type {resource} was set on the bus!")]
+    MissingResource { resource: &'static str },
+    /// a bug in the runtime
+    #[error("Broken runtime invariant: {0}")]
+    RuntimeInvariant(&'static str),
+}
+
+/// Events required by registered stops that nothing handles, see [`DABus::verify`]
+///
+/// [`DABus::verify`]: crate::DABus::verify
+#[derive(Clone, Debug, thiserror::Error)]
+#[cfg_attr(feature = "backtrace_serde", derive(serde::Serialize))]
+#[error("No handlers for required events: {}", .missing.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+pub struct MissingHandlers {
+    pub missing: Vec<RequiredEvent>,
+}
+
+/// An event required by a stop with [`EventRegister::requires`]
+///
+/// [`EventRegister::requires`]: crate::EventRegister::requires
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "backtrace_serde", derive(serde::Serialize))]
+pub struct RequiredEvent {
+    /// the name of the event
+    pub event: &'static str,
+    /// the type name of the stop that requires it, or the name of the protocol
+    pub required_by: &'static str,
 }
 
-#[derive(Debug, Clone, thiserror::Error)]
-#[error("Error while executing bus event:\n{root:#?}")]
+impl Display for RequiredEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (required by {})", self.event, self.required_by)
+    }
+}
+
+/// An `Err` returned by a handler registered with [`EventRegister::fallible_handler`]
+///
+/// [`EventRegister::fallible_handler`]: crate::EventRegister::fallible_handler
+#[derive(Clone, Debug, thiserror::Error)]
+#[cfg_attr(feature = "backtrace_serde", derive(serde::Serialize))]
+#[error("{message}")]
+pub struct HandlerError {
+    /// the type name of the error
+    pub type_name: &'static str,
+    /// the [`Display`] of the error
+    pub message: String,
+    /// the [`Display`] of each error in the [`source`](Error::source) chain, outermost first
+    pub sources: Vec<String>,
+}
+
+impl HandlerError {
+    #[must_use]
+    pub fn new<E: Error + 'static>(err: &E) -> Self {
+        let mut sources = vec![];
+        let mut source = err.source();
+        while let Some(err) = source {
+            sources.push(err.to_string());
+            source = err.source();
+        }
+        Self {
+            type_name: type_name::<E>(),
+            message: err.to_string(),
+            sources,
+        }
+    }
+}
+
+/// The record of a top level call, and all of the nested calls it made
+///
+/// the [`Display`] implementation renders it as a plain text tree, see [`CallTrace::colored`] for a colored one
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "backtrace_serde", derive(serde::Serialize))]
 pub struct CallTrace {
     pub root: Option<CallEvent>,
 }
@@ -40,41 +167,128 @@ impl CallTrace {
     }
 
     /// finds the first failing event in a chain of nested call errors
+    ///
+    /// this follows [`Resolution::NestedCallError`]s down from the root, to the call that failed with a
+    /// bus or handler error. if the top level call failed itself (for example because it has no handler), that
+    /// is the root, which is returned (earlier versions returned `None` there). returns `None` if the call
+    /// succeeded
     #[must_use]
     pub fn source(&self) -> Option<CallEvent> {
-        let mut current_root = self.root.clone()?;
-        if let Resolution::Success = current_root.resolution.clone()? {
-            // no error
-            None?;
-        }
+        self.failing_path()?.last().map(|event| (*event).clone())
+    }
+
+    /// the chain of events from the root to the [`source`] of the error
+    ///
+    /// [`source`]: CallTrace::source
+    pub(crate) fn failing_path(&self) -> Option<Vec<&CallEvent>> {
+        let mut current = self.root.as_ref()?;
+        let mut path = vec![current];
         loop {
-            let last_inner = current_root.inner.last()?.clone();
-            match last_inner.resolution {
-                None | Some(Resolution::Success) => None?, // invalid trace | no error
-                Some(Resolution::NestedCallError) => current_root = last_inner, // more to go
-                Some(Resolution::BusError(..)) => {
-                    current_root = last_inner;
-                    break; // we found it!
+            match current.resolution.as_ref()? {
+                Resolution::Success | Resolution::Defaulted => None?, // no error
+                Resolution::BusError(..) | Resolution::HandlerError(..) => break, // we found it!
+                Resolution::NestedCallError => {
+                    // more to go
+                    current = current.inner.last()?;
+                    path.push(current);
                 }
             }
         }
-        Some(current_root)
+        Some(path)
+    }
+
+    /// a trace of a single call that failed with `err`, replacing any resolution it already had
+    pub(crate) fn bus_error(mut event: CallEvent, err: BaseFireEventError) -> Self {
+        event.resolution = Some(Resolution::BusError(err.into()));
+        event.end_time.get_or_insert_with(SystemTime::now);
+        Self { root: Some(event) }
+    }
+
+    /// renders the trace as a plain text tree, suitable for logs
+    #[must_use]
+    pub const fn plain(&self) -> TraceDisplay<'_> {
+        TraceDisplay::new(self, false)
+    }
+
+    /// renders the trace as a tree, colored with ANSI escape codes for terminals
+    #[must_use]
+    pub const fn colored(&self) -> TraceDisplay<'_> {
+        TraceDisplay::new(self, true)
     }
 
     #[must_use]
     pub fn display(&self) -> String {
-        self.root.as_ref().unwrap().display()
+        self.plain().to_string()
+    }
+
+    /// the trace as a JSON value, including captured arguments and return values
+    #[cfg(feature = "backtrace_serde")]
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("call traces are always serializable")
+    }
+}
+
+impl Display for CallTrace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.plain().fmt(f)
     }
 }
 
+impl std::error::Error for CallTrace {}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "backtrace_serde", derive(serde::Serialize))]
 pub enum Resolution {
     Success,
     BusError(FireEventError),
     NestedCallError,
+    /// a fallible handler returned `Err`
+    HandlerError(HandlerError),
+    /// no stop handles the event, and its default value was returned (see [`DABus::set_default`](crate::DABus::set_default))
+    Defaulted,
+}
+
+impl Resolution {
+    /// a short, stable name for the kind of resolution (used for metrics labels)
+    #[must_use]
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::BusError(..) => "bus_error",
+            Self::NestedCallError => "nested_call_error",
+            Self::HandlerError(..) => "handler_error",
+            Self::Defaulted => "defaulted",
+        }
+    }
+
+    /// checks if the call failed
+    #[must_use]
+    pub const fn is_error(&self) -> bool {
+        !matches!(self, Self::Success | Self::Defaulted)
+    }
+}
+
+impl Display for Resolution {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Success => f.write_str("success"),
+            Self::BusError(err) => write!(f, "bus error: {}", err.base()),
+            Self::NestedCallError => f.write_str("nested call error"),
+            Self::Defaulted => f.write_str("default value"),
+            Self::HandlerError(err) => {
+                write!(f, "handler error ({}): {}", err.type_name, err.message)?;
+                for source in &err.sources {
+                    write!(f, ": {source}")?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "backtrace_serde", derive(serde::Serialize))]
 pub struct CallEvent {
     pub handler_name: &'static str,
     pub handler_args_t: &'static str,
@@ -83,6 +297,29 @@ pub struct CallEvent {
     pub resolution: Option<Resolution>,
     pub return_t: &'static str,
     pub return_v: Option<String>,
+    /// the type name of the stop that handled this call, if any
+    pub stop_name: Option<&'static str>,
+    /// trace and span ids, assigned when the call is dispatched
+    pub context: Option<SpanContext>,
+    pub start_time: Option<SystemTime>,
+    pub end_time: Option<SystemTime>,
+    /// which attempt at the call this is, starting at 0. earlier attempts (see [`RetryPolicy`]) are in
+    /// [`failed_attempts`](CallEvent::failed_attempts)
+    ///
+    /// [`RetryPolicy`]: crate::bus::policy::RetryPolicy
+    pub attempt: u32,
+    /// the earlier attempts at the call, which failed and were retried, oldest first
+    pub failed_attempts: Vec<Self>,
+    /// when the call has to be started by, see [`BusInterface::deadline`]
+    ///
+    /// [`BusInterface::deadline`]: crate::BusInterface::deadline
+    pub deadline: Option<SystemTime>,
+    /// the arguments, captured as structured data
+    #[cfg(feature = "backtrace_serde")]
+    pub args_value: Option<Captured>,
+    /// the return value, captured as structured data
+    #[cfg(feature = "backtrace_serde")]
+    pub return_value: Option<Captured>,
 }
 
 #[cfg(not(feature = "backtrace_track_values"))]
@@ -97,48 +334,30 @@ impl CallEvent {
         _: &At,
     ) -> Self {
         Self {
-            handler_name: def.name,
+            handler_name: def.name(),
             handler_args_t: type_name::<At>(),
             handler_args: None,
             inner: vec![],
             resolution: None,
             return_t: type_name::<Rt>(),
             return_v: None,
+            stop_name: None,
+            context: None,
+            start_time: None,
+            end_time: None,
+            attempt: 0,
+            failed_attempts: vec![],
+            deadline: None,
+            #[cfg(feature = "backtrace_serde")]
+            args_value: None,
+            #[cfg(feature = "backtrace_serde")]
+            return_value: None,
         }
     }
 
     #[inline(always)]
     #[allow(clippy::unused_self)]
     pub fn set_return(&mut self, _: &DynVar) {}
-
-    #[must_use]
-    pub fn display(&self) -> String {
-        const INDENT: &str = "  ";
-        let mut initial = format!(
-            "call: handler {handler_name} (&mut self, args: {args_t}) -> {ret_t}",
-            handler_name = self.handler_name,
-            args_t = self.handler_args_t,
-            ret_t = self.return_t,
-        );
-        let nested: Vec<String> = self.inner.iter().map(|event| event.display()).collect();
-        let nested_calls: bool = !nested.is_empty();
-        if nested_calls {
-            initial.push('\n');
-        } else {
-            write!(initial, " ::: {:?}", self.resolution.as_ref().unwrap()).unwrap();
-        }
-        for n in nested {
-            let indented_n = n
-                .split('\n')
-                .map(|line| INDENT.to_string() + line + "\n")
-                .collect::<String>();
-            initial.push_str(&indented_n);
-        }
-        if nested_calls {
-            write!(initial, "ret: {:?}", self.resolution.as_ref().unwrap()).unwrap();
-        }
-        initial
-    }
 }
 
 #[cfg(feature = "backtrace_track_values")]
@@ -152,24 +371,42 @@ impl CallEvent {
         args: &At,
     ) -> Self {
         Self {
-            handler_name: def.name,
+            handler_name: def.name(),
             handler_args_t: type_name::<At>(),
             handler_args: Some(format!("{:#?}", args.as_dbg())),
             inner: vec![],
             resolution: None,
             return_t: type_name::<Rt>(),
             return_v: None,
+            stop_name: None,
+            context: None,
+            start_time: None,
+            end_time: None,
+            attempt: 0,
+            failed_attempts: vec![],
+            deadline: None,
+            #[cfg(feature = "backtrace_serde")]
+            args_value: None,
+            #[cfg(feature = "backtrace_serde")]
+            return_value: None,
         }
     }
 
     pub fn set_return(&mut self, return_v: &DynVar) {
         debug_assert!(self.return_v.is_none());
-        let fmt = format!("{:?}", return_v.as_dbg());
+        let fmt = format!("{:?}", return_v.debug_inner());
         self.return_v = Some(fmt);
     }
 }
 
 impl CallEvent {
+    /// marks the call as dispatched to `stop` (if one was found)
+    pub fn begin(&mut self, context: SpanContext, stop: Option<&'static str>) {
+        self.context = Some(context);
+        self.stop_name = stop;
+        self.start_time = Some(SystemTime::now());
+    }
+
     pub fn resolve(&mut self, resolution: Resolution) {
         debug_assert!(
             self.resolution.is_none(),
@@ -178,9 +415,19 @@ impl CallEvent {
             self.resolution
         );
         self.resolution = Some(resolution);
+        self.end_time = Some(SystemTime::now());
     }
 
     pub fn push_inner(&mut self, event: Self) {
         self.inner.push(event);
     }
+
+    /// renders this event and its nested calls as a plain text tree, see [`CallTrace::plain`]
+    #[must_use]
+    pub fn display(&self) -> String {
+        CallTrace {
+            root: Some(self.clone()),
+        }
+        .display()
+    }
 }
@@ -0,0 +1,276 @@
+//! exporting call traces as OpenTelemetry spans
+//!
+//! every call on the bus is assigned a [`SpanContext`] when it is dispatched. nested calls share the
+//! trace id of the call that made them, so a whole [`CallTrace`] maps onto one OpenTelemetry trace.
+//!
+//! completed top level calls are handed to the [`TraceExporter`] set with [`DABus::set_exporter`].
+//! [`OtlpJsonExporter`] turns them into OTLP-JSON (`ExportTraceServiceRequest`) documents.
+//!
+//! [`DABus::set_exporter`]: crate::DABus::set_exporter
+
+use std::{
+    collections::hash_map::RandomState,
+    fmt::{self, Write as _},
+    fs::{File, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    io::{self, Write as _},
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::bus::error::{CallEvent, CallTrace, Resolution};
+
+/// identifier shared by every call in a single top level call tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId(pub u128);
+
+/// identifier of a single call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanId(pub u64);
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl fmt::Display for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+#[cfg(feature = "backtrace_serde")]
+impl serde::Serialize for TraceId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "backtrace_serde")]
+impl serde::Serialize for SpanId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// the trace and span ids of a call, available to handlers through [`BusInterface::span_context`]
+///
+/// [`BusInterface::span_context`]: crate::BusInterface::span_context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "backtrace_serde", derive(serde::Serialize))]
+pub struct SpanContext {
+    pub trace_id: TraceId,
+    pub span_id: SpanId,
+}
+
+impl SpanContext {
+    /// a new context, starting a new trace
+    #[must_use]
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: TraceId(u128::from(random_u64()) << 64 | u128::from(random_u64())),
+            span_id: SpanId(random_u64()),
+        }
+    }
+
+    /// a new context in the same trace as `self`
+    #[must_use]
+    pub fn new_child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: SpanId(random_u64()),
+        }
+    }
+}
+
+/// non-zero pseudo random ids, without pulling in a rng dependency
+fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    loop {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        let id = hasher.finish();
+        if id != 0 {
+            break id;
+        }
+    }
+}
+
+/// receives every completed top level call on a bus, see [`DABus::set_exporter`]
+///
+/// this is implemented for closures taking a `&CallTrace`
+///
+/// [`DABus::set_exporter`]: crate::DABus::set_exporter
+pub trait TraceExporter {
+    fn export(&mut self, trace: &CallTrace);
+}
+
+impl<F: FnMut(&CallTrace)> TraceExporter for F {
+    fn export(&mut self, trace: &CallTrace) {
+        self(trace);
+    }
+}
+
+enum Sink {
+    File(File),
+    Callback(Box<dyn FnMut(String) + Send + Sync + 'static>),
+}
+
+/// Exports call traces as OTLP-JSON, one `ExportTraceServiceRequest` per top level call
+pub struct OtlpJsonExporter {
+    sink: Sink,
+    service_name: String,
+}
+
+impl OtlpJsonExporter {
+    /// appends one JSON document per line to the file at `path`, creating it if needed
+    ///
+    /// # Errors
+    ///
+    /// if the file could not be opened
+    pub fn to_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            sink: Sink::File(file),
+            service_name: String::from("dabus"),
+        })
+    }
+
+    /// hands each JSON document to `callback`
+    pub fn to_callback(callback: impl FnMut(String) + Send + Sync + 'static) -> Self {
+        Self {
+            sink: Sink::Callback(Box::new(callback)),
+            service_name: String::from("dabus"),
+        }
+    }
+
+    /// sets the `service.name` resource attribute (defaults to `dabus`)
+    #[must_use]
+    pub fn service_name(mut self, name: impl Into<String>) -> Self {
+        self.service_name = name.into();
+        self
+    }
+}
+
+impl TraceExporter for OtlpJsonExporter {
+    fn export(&mut self, trace: &CallTrace) {
+        let json = to_otlp_json(trace, &self.service_name);
+        match &mut self.sink {
+            Sink::File(file) => {
+                if let Err(err) = writeln!(file, "{json}") {
+                    error!("Failed to export call trace: {}", err);
+                }
+            }
+            Sink::Callback(callback) => callback(json),
+        }
+    }
+}
+
+/// converts a call trace to an OTLP-JSON `ExportTraceServiceRequest`
+///
+/// calls that were never dispatched (and thus have no [`SpanContext`]) are skipped
+#[must_use]
+pub fn to_otlp_json(trace: &CallTrace, service_name: &str) -> String {
+    let mut spans = vec![];
+    if let Some(root) = &trace.root {
+        collect_spans(root, None, &mut spans);
+    }
+    format!(
+        concat!(
+            r#"{{"resourceSpans":[{{"resource":{{"attributes":[{}]}},"#,
+            r#""scopeSpans":[{{"scope":{{"name":"dabus","version":"{}"}},"spans":[{}]}}]}}]}}"#
+        ),
+        attribute("service.name", service_name),
+        env!("CARGO_PKG_VERSION"),
+        spans.join(",")
+    )
+}
+
+fn collect_spans(event: &CallEvent, parent: Option<SpanId>, spans: &mut Vec<String>) {
+    let Some(context) = event.context else {
+        return;
+    };
+    let mut attributes = vec![
+        attribute("dabus.args_type", event.handler_args_t),
+        attribute("dabus.return_type", event.return_t),
+    ];
+    if let Some(stop) = event.stop_name {
+        attributes.push(attribute("dabus.stop", stop));
+    }
+    if let Some(args) = &event.handler_args {
+        attributes.push(attribute("dabus.args", args));
+    }
+    if let Some(return_v) = &event.return_v {
+        attributes.push(attribute("dabus.return", return_v));
+    }
+    if event.attempt > 0 {
+        attributes.push(attribute("dabus.attempt", &event.attempt.to_string()));
+    }
+    let status = match &event.resolution {
+        None => String::from(r#"{"code":0}"#),
+        Some(Resolution::Success) => String::from(r#"{"code":1}"#),
+        Some(Resolution::Defaulted) => {
+            attributes.push(attribute("dabus.resolution", Resolution::Defaulted.kind()));
+            String::from(r#"{"code":1}"#)
+        }
+        Some(resolution) => {
+            attributes.push(attribute("dabus.resolution", resolution.kind()));
+            format!(
+                r#"{{"code":2,"message":"{}"}}"#,
+                escape(&resolution.to_string())
+            )
+        }
+    };
+    let mut span = format!(
+        r#"{{"traceId":"{}","spanId":"{}","#,
+        context.trace_id, context.span_id
+    );
+    if let Some(parent) = parent {
+        write!(span, r#""parentSpanId":"{parent}","#).unwrap();
+    }
+    write!(
+        span,
+        r#""name":"{}","kind":1,"startTimeUnixNano":"{}","endTimeUnixNano":"{}","attributes":[{}],"status":{}}}"#,
+        escape(event.handler_name),
+        unix_nanos(event.start_time),
+        unix_nanos(event.end_time.or(event.start_time)),
+        attributes.join(","),
+        status,
+    )
+    .unwrap();
+    spans.push(span);
+    for inner in event.failed_attempts.iter().chain(&event.inner) {
+        collect_spans(inner, Some(context.span_id), spans);
+    }
+}
+
+fn attribute(key: &str, value: &str) -> String {
+    format!(
+        r#"{{"key":"{}","value":{{"stringValue":"{}"}}}}"#,
+        escape(key),
+        escape(value)
+    )
+}
+
+fn unix_nanos(time: Option<SystemTime>) -> u128 {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since_epoch| since_epoch.as_nanos())
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
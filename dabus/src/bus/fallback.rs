@@ -0,0 +1,57 @@
+//! handling events that no stop handles
+//!
+//! when no registered stop handles an event, the bus will (in order)
+//! - return the default value set for it with [`DABus::set_default`]
+//! - pass it to the stop handling [`DEAD_LETTER`] (if any), as an [`UnhandledEvent`]
+//! - fail the call with [`BaseFireEventError::NoHandler`]
+//!
+//! ```rust
+//! use dabus::{BusInterface, BusStop, EventRegister, bus::fallback::{DEAD_LETTER, UnhandledEvent}, extras::DynVar};
+//!
+//! #[derive(Debug)]
+//! struct DeadLetters {
+//!     unhandled: Vec<&'static str>,
+//! }
+//!
+//! impl DeadLetters {
+//!     async fn dead_letter(&mut self, event: UnhandledEvent, _i: BusInterface) -> Option<DynVar> {
+//!         self.unhandled.push(event.name);
+//!         // this must have the return type of the event, or the caller will get a type mismatch error
+//!         (event.return_type == std::any::type_name::<()>()).then(|| DynVar::new(()))
+//!     }
+//! }
+//!
+//! impl BusStop for DeadLetters {
+//!     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+//!         h.handler(DEAD_LETTER, Self::dead_letter)
+//!     }
+//! }
+//! ```
+//!
+//! stops can also declare the events that they fire with [`EventRegister::requires`], so that missing handlers can be
+//! found up front with [`DABus::verify`] (or on every top level call, with [`DABus::set_strict`])
+//!
+//! [`DABus::set_default`]: crate::DABus::set_default
+//! [`DABus::verify`]: crate::DABus::verify
+//! [`DABus::set_strict`]: crate::DABus::set_strict
+//! [`BaseFireEventError::NoHandler`]: crate::bus::error::BaseFireEventError::NoHandler
+//! [`EventRegister::requires`]: crate::EventRegister::requires
+
+use crate::core::dyn_var::DynVar;
+
+/// An event that no stop handles, see [`DEAD_LETTER`]
+#[derive(Debug)]
+pub struct UnhandledEvent {
+    /// the name of the event
+    pub name: &'static str,
+    /// the type name of the event's arguments
+    pub args_type: &'static str,
+    /// the type name of the event's return value
+    pub return_type: &'static str,
+    /// the arguments the event was fired with
+    pub args: DynVar,
+}
+
+// fired with every event that no stop handles. returning `None` fails the call with `NoHandler`,
+// and `Some` must contain a value of the event's return type
+crate::event!(pub DEAD_LETTER, UnhandledEvent, Option<DynVar>);
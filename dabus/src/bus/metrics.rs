@@ -0,0 +1,286 @@
+//! call metrics collected by the bus runtime
+//!
+//! every call made on a bus (top level or nested) is counted per event and per stop type,
+//! and a snapshot of the numbers can be taken at any time with [`DABus::metrics`].
+//!
+//! [`DABus::metrics`]: crate::DABus::metrics
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use crate::bus::error::Resolution;
+
+/// the stop name used for calls that never reached a stop (for example, when no handler was found)
+pub const NO_STOP: &str = "<none>";
+
+/// upper bounds (in seconds) of the latency histogram buckets
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.000_05, 0.000_1, 0.000_25, 0.000_5, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.05, 0.1, 1.0,
+];
+
+/// what metrics are broken down by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MetricsKey {
+    /// the name of the event that was called
+    pub event: &'static str,
+    /// the type name of the stop that handled it, or [`NO_STOP`]
+    pub stop: &'static str,
+}
+
+/// latency histogram for a single event/stop pair
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyHistogram {
+    /// non-cumulative counts for each bucket of [`LATENCY_BUCKETS`], plus one overflow bucket
+    pub buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    /// total time spent in calls, in seconds
+    pub sum: f64,
+    /// number of observed calls
+    pub count: u64,
+}
+
+impl LatencyHistogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [0; LATENCY_BUCKETS.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += secs;
+        self.count += 1;
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// the numbers collected for a single event/stop pair
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallMetrics {
+    /// number of calls started
+    pub calls: u64,
+    /// number of calls that did not succeed, by [`Resolution::kind`] (calls answered with a default value are not errors)
+    pub errors: BTreeMap<&'static str, u64>,
+    /// number of calls that have started but not yet finished (or been abandoned by their caller)
+    pub in_flight: u64,
+    /// time taken by finished calls
+    pub latency: LatencyHistogram,
+}
+
+/// Call metrics for a bus, see [`DABus::metrics`]
+///
+/// [`DABus::metrics`]: crate::DABus::metrics
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    /// keyed by event, and then by stop
+    calls: BTreeMap<&'static str, BTreeMap<&'static str, CallMetrics>>,
+}
+
+impl Metrics {
+    pub(crate) fn call_finished(
+        &mut self,
+        event: &'static str,
+        stop: &'static str,
+        resolution: &Resolution,
+        latency: Duration,
+    ) {
+        let metrics = self.entry(event, stop);
+        metrics.latency.observe(latency);
+        if resolution.is_error() {
+            *metrics.errors.entry(resolution.kind()).or_default() += 1;
+        }
+    }
+
+    /// gets the metrics for calls of `event` handled by `stop`
+    #[must_use]
+    pub fn get(&self, event: &str, stop: &str) -> Option<&CallMetrics> {
+        self.calls.get(event)?.get(stop)
+    }
+
+    /// iterates over the collected metrics, ordered by event and then by stop
+    pub fn iter(&self) -> impl Iterator<Item = (MetricsKey, &CallMetrics)> {
+        self.calls.iter().flat_map(|(&event, stops)| {
+            stops
+                .iter()
+                .map(move |(&stop, metrics)| (MetricsKey { event, stop }, metrics))
+        })
+    }
+
+    fn entry(&mut self, event: &'static str, stop: &'static str) -> &mut CallMetrics {
+        self.calls
+            .entry(event)
+            .or_default()
+            .entry(stop)
+            .or_default()
+    }
+
+    /// renders the metrics in the Prometheus text exposition format
+    #[must_use]
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "dabus_calls_total",
+            "counter",
+            "Number of calls made on the bus.",
+        );
+        for (key, metrics) in self.iter() {
+            writeln!(
+                out,
+                "dabus_calls_total{{{}}} {}",
+                labels(&key),
+                metrics.calls
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "dabus_call_errors_total",
+            "counter",
+            "Number of calls that did not succeed, by resolution.",
+        );
+        for (key, metrics) in self.iter() {
+            for (resolution, count) in &metrics.errors {
+                writeln!(
+                    out,
+                    "dabus_call_errors_total{{{},resolution=\"{}\"}} {}",
+                    labels(&key),
+                    resolution,
+                    count
+                )
+                .unwrap();
+            }
+        }
+
+        header(
+            &mut out,
+            "dabus_calls_in_flight",
+            "gauge",
+            "Number of calls currently being handled.",
+        );
+        for (key, metrics) in self.iter() {
+            writeln!(
+                out,
+                "dabus_calls_in_flight{{{}}} {}",
+                labels(&key),
+                metrics.in_flight
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "dabus_call_duration_seconds",
+            "histogram",
+            "Time taken by calls on the bus.",
+        );
+        for (key, metrics) in self.iter() {
+            let labels = labels(&key);
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(metrics.latency.buckets) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "dabus_call_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {cumulative}"
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "dabus_call_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                metrics.latency.count
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "dabus_call_duration_seconds_sum{{{labels}}} {}",
+                metrics.latency.sum
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "dabus_call_duration_seconds_count{{{labels}}} {}",
+                metrics.latency.count
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+/// counts a call as in flight until it is dropped, so that calls abandoned part way through (when the future of a
+/// top level call is dropped) are not counted forever
+pub(crate) struct InFlight {
+    metrics: Arc<Mutex<Metrics>>,
+    key: MetricsKey,
+}
+
+impl InFlight {
+    /// counts a call of `event` handled by `stop`
+    pub(crate) fn start(
+        metrics: &Arc<Mutex<Metrics>>,
+        event: &'static str,
+        stop: &'static str,
+    ) -> Self {
+        let mut locked = lock(metrics);
+        let call = locked.entry(event, stop);
+        call.calls += 1;
+        call.in_flight += 1;
+        drop(locked);
+        Self {
+            metrics: metrics.clone(),
+            key: MetricsKey { event, stop },
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut metrics = lock(&self.metrics);
+        let call = metrics.entry(self.key.event, self.key.stop);
+        call.in_flight = call.in_flight.saturating_sub(1);
+    }
+}
+
+/// locks the metrics of a bus, which are only ever left part way through an update if counting panicked
+pub(crate) fn lock(metrics: &Mutex<Metrics>) -> MutexGuard<'_, Metrics> {
+    metrics.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} {kind}").unwrap();
+}
+
+fn labels(key: &MetricsKey) -> String {
+    format!(
+        "event=\"{}\",stop=\"{}\"",
+        escape_label(key.event),
+        escape_label(key.stop)
+    )
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
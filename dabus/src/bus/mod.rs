@@ -1,45 +1,76 @@
 //! the core of DABus
 
+pub mod blocking;
+mod call_span;
+mod call_tree;
+#[cfg(feature = "backtrace_serde")]
+pub mod capture;
 pub mod error;
+pub mod export;
+pub mod fallback;
+pub mod metrics;
+pub mod policy;
+pub mod render;
 
 use core::any::TypeId;
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    any::type_name,
+    collections::BTreeMap,
+    fmt::Debug,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
 
-use flume::{r#async::RecvFut, Receiver, Sender};
-use futures::future::BoxFuture;
+use flume::{Receiver, Sender};
+use futures::{future, future::BoxFuture, FutureExt};
 
 use crate::{
     bus::error::{CallEvent, CallTrace},
     core::dyn_var::DynVar,
-    event::EventDef,
-    interface::{BusInterface, BusInterfaceEvent},
-    stop::{BusStopContainer, BusStopMechContainer},
+    event::{async_fn_ptr::HandlerOutput, protocol::Protocol, EventDef},
+    interface::{BusInterface, BusInterfaceEvent, CallInfo, Resources, Responder},
+    stop::{fn_stop::FnStop, BusStopContainer, BusStopMechContainer, BusStopReq, StopId},
     unique_type,
-    util::{
-        async_util::{OneOf, OneOfResult},
-        dyn_debug::DynDebug,
-    },
+    util::dyn_debug::DynDebug,
     BusStop, EventRegister,
 };
-use error::{BaseFireEventError, FireEventError};
+use blocking::Spawner;
+use call_span::CallSpan;
+use call_tree::{CallTree, Frame, FrameEvent, FrameId, QueuedCall, Retry};
+use error::{BaseFireEventError, FireEventError, MissingHandlers, RequiredEvent};
+use export::{SpanContext, TraceExporter};
+use fallback::{UnhandledEvent, DEAD_LETTER};
+use metrics::{InFlight, Metrics};
+use policy::{
+    CircuitBreaker, EventPolicy, OverLimit, Policy, RateLimit, RetryPolicy, Sleeper, ThreadSleeper,
+};
 
 use self::error::Resolution;
 
-enum Frame {
-    ReadyToPoll {
-        interface_recv: Receiver<BusInterfaceEvent>,
-        recev_fut: RecvFut<'static, BusInterfaceEvent>,
-        handler: Arc<BusStopContainer>,
-        handler_fut: BoxFuture<'static, DynVar>,
-        local_trace_data: CallEvent,
-    },
-    AwaitingNestedCall {
-        interface_recv: Receiver<BusInterfaceEvent>,
-        handler: Arc<BusStopContainer>,
-        handler_fut: BoxFuture<'static, DynVar>,
-        responder: Sender<Result<DynVar, CallTrace>>,
-        local_trace_data: CallEvent,
+/// the default value of [`DABus::set_max_depth`]
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// produces the default value of an event
+type DefaultFn = Box<dyn Fn() -> DynVar + Send + Sync + 'static>;
+
+/// what to do with a call
+enum Dispatch {
+    /// run it on this stop (as an [`UnhandledEvent`] if `dead_letter` is set), which is shared with other frames
+    /// if the handler is a shared handler
+    Start {
+        stop: Arc<BusStopContainer>,
+        shared: bool,
+        dead_letter: bool,
     },
+    /// return the event's default value
+    Default,
+    /// wait for the stop that handles it to be free (or for a call of the event to finish, if it is at its
+    /// in flight limit)
+    Queue,
+    /// wait for the event's rate limit to allow the call
+    Wait(Duration),
+    Fail(BaseFireEventError),
 }
 
 /// Messaging bus and handler holder.
@@ -66,10 +97,39 @@ enum Frame {
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Debug)]
 #[allow(clippy::module_name_repetitions)]
 pub struct DABus {
-    registered_stops: Vec<BusStopContainer>,
+    /// registered stops, except for the ones currently handling a call
+    registered_stops: BTreeMap<StopId, BusStopContainer>,
+    /// stops lent to shared handlers, which are put back once none of them are running
+    shared_stops: BTreeMap<StopId, Arc<BusStopContainer>>,
+    /// which stop handles each event, including stops that are currently handling a call
+    handler_index: BTreeMap<TypeId, StopId>,
+    next_stop_id: StopId,
+    /// `None` until the first call, shared with the calls that are in flight
+    metrics: Option<Arc<Mutex<Metrics>>>,
+    exporter: Option<Box<dyn TraceExporter + Send + Sync + 'static>>,
+    max_depth: Option<usize>,
+    call_budget: Option<usize>,
+    defaults: BTreeMap<TypeId, DefaultFn>,
+    strict: bool,
+    /// policies set on the bus
+    policies: BTreeMap<TypeId, EventPolicy>,
+    /// policies set by stops, which take precedence
+    stop_policies: BTreeMap<(StopId, TypeId), EventPolicy>,
+    limits: BTreeMap<TypeId, RateLimit>,
+    /// `None` for [`ThreadSleeper`]
+    sleeper: Option<Box<dyn Sleeper + Send + Sync + 'static>>,
+    /// `None` for [`ThreadSpawner`](blocking::ThreadSpawner)
+    spawner: Option<Arc<dyn Spawner + Send + Sync + 'static>>,
+    timeout: Option<Duration>,
+    /// `None` until a resource is set
+    resources: Option<Arc<Resources>>,
+    #[cfg(feature = "backtrace_serde")]
+    capture_limit: usize,
+    /// types registered with [`DABus::capture`]
+    #[cfg(feature = "backtrace_serde")]
+    captured_types: BTreeMap<TypeId, capture::SerializeFn>,
 }
 
 impl DABus {
@@ -77,244 +137,1340 @@ impl DABus {
     #[must_use]
     pub const fn new() -> Self {
         Self {
-            registered_stops: vec![],
+            registered_stops: BTreeMap::new(),
+            shared_stops: BTreeMap::new(),
+            handler_index: BTreeMap::new(),
+            next_stop_id: 0,
+            metrics: None,
+            exporter: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            call_budget: None,
+            defaults: BTreeMap::new(),
+            strict: false,
+            policies: BTreeMap::new(),
+            stop_policies: BTreeMap::new(),
+            limits: BTreeMap::new(),
+            sleeper: None,
+            spawner: None,
+            timeout: None,
+            resources: None,
+            #[cfg(feature = "backtrace_serde")]
+            capture_limit: capture::DEFAULT_CAPTURE_LIMIT,
+            #[cfg(feature = "backtrace_serde")]
+            captured_types: BTreeMap::new(),
         }
     }
 
+    /// Sets how deeply calls may be nested (the top level call has a depth of 0), or `None` for no limit.
+    /// nested calls past the limit fail with [`BaseFireEventError::DepthExceeded`]
+    ///
+    /// defaults to [`DEFAULT_MAX_DEPTH`]
+    pub fn set_max_depth(&mut self, limit: Option<usize>) {
+        self.max_depth = limit;
+    }
+
+    /// Sets how many nested calls a top level call may make in total (including calls made by nested calls),
+    /// or `None` for no limit. nested calls past the limit fail with [`BaseFireEventError::BudgetExceeded`]
+    ///
+    /// defaults to `None`
+    pub fn set_call_budget(&mut self, limit: Option<usize>) {
+        self.call_budget = limit;
+    }
+
+    /// Sets a deadline for starting calls, measured from when each top level call is fired, or `None` for no limit.
+    /// the top level call and the calls it makes (see [`BusInterface::deadline`]) fail with
+    /// [`BaseFireEventError::DeadlineExceeded`] if they have not been started by then.
+    ///
+    /// this does not bound how long a call takes: handlers that are already running are not stopped, so a call can
+    /// finish well after its deadline. handlers that want to give up early can check [`BusInterface::deadline`]
+    ///
+    /// defaults to `None`
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Sets a value shared by the whole bus, which handlers can ask for with
+    /// [`Resource<T>`](crate::event::extract::Resource), replacing any previous value of the same type
+    pub fn set_resource<T: Send + Sync + 'static>(&mut self, resource: T) {
+        Arc::make_mut(self.resources.get_or_insert_with(Arc::default))
+            .insert(TypeId::of::<T>(), Arc::new(resource));
+    }
+
+    /// Sets the value returned for `def` when no stop handles it, see [`fallback`]
+    pub fn set_default<Tag, At, Rt>(&mut self, def: &'static EventDef<Tag, At, Rt>, value: Rt)
+    where
+        Tag: unique_type::Unique,
+        Rt: Clone + DynDebug + Sync + Send + 'static,
+    {
+        let _ = def;
+        self.defaults.insert(
+            TypeId::of::<Tag>(),
+            Box::new(move || DynVar::new(value.clone())),
+        );
+    }
+
+    /// Enables or disables strict mode. in strict mode, top level calls fail with [`BaseFireEventError::MissingHandlers`]
+    /// unless [`DABus::verify`] succeeds
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Retries calls of `def` when they fail, unless the stop that handles it sets its own policy (see [`policy`])
+    pub fn set_retry<Tag, At, Rt>(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        policy: RetryPolicy,
+    ) where
+        Tag: unique_type::Unique,
+        At: DynDebug + Clone + Sync + Send + 'static,
+    {
+        self.policies
+            .entry(def.tag_id())
+            .or_default()
+            .set(Policy::Retry(policy, DynVar::clone_as::<At>));
+    }
+
+    /// Fails calls of `def` without running them after too many failures, unless the stop that handles it
+    /// sets its own circuit breaker (see [`policy`])
+    pub fn set_circuit_breaker<Tag, At, Rt>(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        breaker: CircuitBreaker,
+    ) where
+        Tag: unique_type::Unique,
+    {
+        self.policies
+            .entry(def.tag_id())
+            .or_default()
+            .set(Policy::Breaker(breaker));
+    }
+
+    /// Limits how often `def` is run, and how many calls of it run at once, replacing any previous limit (see [`RateLimit`])
+    pub fn set_rate_limit<Tag, At, Rt>(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        limit: RateLimit,
+    ) where
+        Tag: unique_type::Unique,
+    {
+        self.limits.insert(def.tag_id(), limit);
+    }
+
+    /// Sets what is used to wait before retrying calls (or running rate limited ones), replacing the default [`ThreadSleeper`]
+    pub fn set_sleeper<S: Sleeper + Send + Sync + 'static>(&mut self, sleeper: S) {
+        self.sleeper = Some(Box::new(sleeper));
+    }
+
+    /// Sets what runs blocking handlers (see [`blocking`]), replacing the default [`ThreadSpawner`](blocking::ThreadSpawner)
+    pub fn set_spawner<S: Spawner + Send + Sync + 'static>(&mut self, spawner: S) {
+        self.spawner = Some(Arc::new(spawner));
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        match &self.sleeper {
+            Some(sleeper) => sleeper.sleep(duration),
+            None => ThreadSleeper.sleep(duration),
+        }
+    }
+
+    /// the retry policy for `def`, if it has one
+    fn retry_policy(&self, def: TypeId) -> Option<(RetryPolicy, policy::CloneArgs)> {
+        self.handler_index
+            .get(&def)
+            .and_then(|id| self.stop_policies.get(&(*id, def)))
+            .and_then(|policy| policy.retry)
+            .or_else(|| self.policies.get(&def).and_then(|policy| policy.retry))
+    }
+
+    /// the circuit breaker for `def`, if it has one
+    fn breaker(&mut self, def: TypeId) -> Option<&mut CircuitBreaker> {
+        let stop = self.handler_index.get(&def).copied();
+        let stop_breaker = stop.is_some_and(|id| {
+            self.stop_policies
+                .get(&(id, def))
+                .is_some_and(|policy| policy.breaker.is_some())
+        });
+        if stop_breaker {
+            stop.and_then(|id| self.stop_policies.get_mut(&(id, def)))
+        } else {
+            self.policies.get_mut(&def)
+        }
+        .and_then(|policy| policy.breaker.as_mut())
+    }
+
+    /// Checks that every event required by a registered stop (see [`EventRegister::requires`]) has a handler or a default value
+    ///
+    /// # Errors
+    ///
+    /// lists the required events that are not handled
+    pub fn verify(&self) -> Result<(), MissingHandlers> {
+        let missing = self
+            .registered_stops
+            .values()
+            .flat_map(|stop| {
+                stop.required().iter().map(|(def, event)| {
+                    (
+                        *def,
+                        RequiredEvent {
+                            event,
+                            required_by: stop.name(),
+                        },
+                    )
+                })
+            })
+            .filter(|(def, _)| {
+                !self.defaults.contains_key(def) && !self.handler_index.contains_key(def)
+            })
+            .map(|(_, required)| required)
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingHandlers { missing })
+        }
+    }
+
+    /// Checks that every event of the protocol `P` has a handler or a default value, like [`DABus::verify`]
+    ///
+    /// # Errors
+    ///
+    /// lists the events that are not handled, as required by the protocol
+    pub fn verify_protocol<P: Protocol>(&self) -> Result<(), MissingHandlers> {
+        let missing = P::events()
+            .into_iter()
+            .filter(|(def, _)| {
+                !self.defaults.contains_key(def) && !self.handler_index.contains_key(def)
+            })
+            .map(|(_, event)| RequiredEvent {
+                event,
+                required_by: P::NAME,
+            })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingHandlers { missing })
+        }
+    }
+
+    /// Sets the maximum size (in bytes of JSON) of each argument and return value captured in call traces.
+    /// larger values are recorded as [`capture::Captured::Truncated`]
+    #[cfg(feature = "backtrace_serde")]
+    pub fn set_capture_limit(&mut self, limit: usize) {
+        self.capture_limit = limit;
+    }
+
+    /// Captures arguments and return values of type `T` in call traces, see [`capture`]
+    ///
+    /// with the `nightly` feature every [`Serialize`](serde::Serialize) type is captured, so this is only needed
+    /// without it
+    #[cfg(feature = "backtrace_serde")]
+    pub fn capture<T: serde::Serialize + DynDebug + 'static>(&mut self) {
+        self.captured_types
+            .insert(TypeId::of::<T>(), capture::serialize_as::<T>);
+    }
+
+    /// Sets the exporter that every completed top level call trace is handed to, replacing any previous one
+    ///
+    /// see [`export::OtlpJsonExporter`] for exporting them as OpenTelemetry spans
+    pub fn set_exporter<E: TraceExporter + Send + Sync + 'static>(&mut self, exporter: E) {
+        self.exporter = Some(Box::new(exporter));
+    }
+
+    /// Takes a snapshot of the call metrics collected by this bus
+    ///
+    /// to export them, see [`Metrics::to_prometheus`]
+    #[must_use]
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+            .as_deref()
+            .map_or_else(Metrics::default, |metrics| metrics::lock(metrics).clone())
+    }
+
     /// Registers a handler with the bus, so that it can be used to handle events on this bus instance.
-    pub fn register<T: BusStop + Debug + Send + Sync + 'static>(&mut self, stop: T) {
+    ///
+    /// only one stop can handle each event, so a stop has to be removed (see [`DABus::remove`]) before
+    /// another one can take over its events
+    ///
+    /// the returned handle can be used to remove this stop again
+    ///
+    /// # Panics
+    ///
+    /// if another stop already handles one of the events `stop` handles (see [`DABus::has_handler`])
+    pub fn register<T: BusStop + Debug + Send + Sync + 'static>(&mut self, stop: T) -> StopHandle {
         info!("Registering stop {:?}", stop);
+        let register = <T as BusStop>::registered_handlers(EventRegister::new());
         debug!(
             "Stop handlers: {:#?}",
-            <T as BusStop>::registered_handlers(EventRegister::new())
+            register
                 .handlers
-                .into_iter()
-                .map(|h| { h.2 })
+                .iter()
+                .map(|h| &h.2)
+                .chain(register.shared_handlers.iter().map(|h| &h.2))
                 .collect::<Vec<_>>()
         );
-        self.registered_stops
-            .push(BusStopContainer::new(Box::new(BusStopMechContainer::new(
-                stop,
-            ))));
+        let handle = self.insert_stop(Box::new(BusStopMechContainer::new(stop)));
+        for (def, policy) in register.policies {
+            self.stop_policies
+                .entry((handle.id, def))
+                .or_default()
+                .set(policy);
+        }
+        handle
+    }
+
+    /// Registers a closure as the handler for `def`, without defining a stop for it
+    ///
+    /// the closure is registered as a [`FnStop`] named after its type, which includes where it was declared.
+    /// see [`DABus::register`]
+    ///
+    /// # Panics
+    ///
+    /// if another stop already handles `def`
+    ///
+    /// ```rust
+    /// # use dabus::{event, DABus};
+    /// event!(DOUBLE, u32, u32);
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let mut bus = DABus::new();
+    /// bus.register_fn(DOUBLE, |x, _i| async move { x * 2 });
+    /// assert_eq!(bus.fire(DOUBLE, 2).await.unwrap().ret(), 4);
+    /// # });
+    /// ```
+    pub fn register_fn<Tag, At, Rt, F, Fut>(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        func: F,
+    ) -> StopHandle
+    where
+        Tag: unique_type::Unique + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        Rt: DynDebug + Send + Sync + 'static,
+        F: Fn(At, BusInterface) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Rt> + Send + 'static,
+    {
+        self.register_fn_stop(FnStop::new(type_name::<F>()).handler(def, func))
+    }
+
+    /// Registers a stop made of closures, see [`FnStop`] and [`DABus::register`]
+    ///
+    /// # Panics
+    ///
+    /// if another stop already handles one of the events `stop` handles
+    pub fn register_fn_stop<S: Send + Sync + 'static>(&mut self, stop: FnStop<S>) -> StopHandle {
+        info!("Registering stop {:?}", stop);
+        self.insert_stop(Box::new(stop))
+    }
+
+    fn insert_stop(&mut self, stop: Box<dyn BusStopReq + Send + Sync + 'static>) -> StopHandle {
+        let id = self.next_stop_id;
+        let stop = BusStopContainer::new(stop, id);
+        for def in stop.handled() {
+            if let Some(other) = self.handler_index.get(def) {
+                panic!(
+                    "{} handles event {:?}, which is already handled by {} (only one stop can handle each event)",
+                    stop.name(),
+                    def,
+                    self.registered_stops
+                        .get(other)
+                        .map_or("a stop that was lost while handling a call", |other| {
+                            other.name()
+                        })
+                );
+            }
+        }
+        self.next_stop_id += 1;
+        for def in stop.handled() {
+            self.handler_index.insert(*def, id);
+        }
+        self.registered_stops.insert(id, stop);
+        StopHandle { id }
+    }
+
+    /// Removes the stop identified by `handle` (which must come from this bus), returning if it was still registered
+    ///
+    /// the events it handled can then be handled by another stop
+    pub fn remove(&mut self, handle: StopHandle) -> bool {
+        let removed = self.registered_stops.remove(&handle.id).is_some();
+        if removed {
+            self.stop_policies.retain(|(id, _), _| *id != handle.id);
+            self.rebuild_index();
+        }
+        removed
     }
 
     /// Attempts to collect all handlers with the specified type and returns them. this is rather blunt,
     /// as there is no way of specifying a particular handler instance, but it is still usefull.
+    ///
+    /// to remove a particular instance, see [`DABus::remove`]
     pub fn deregister<T: BusStop + Debug + Send + Sync + 'static>(&mut self) -> Vec<T> {
         let stop = self
             .registered_stops
-            .extract_if(|stop| {
-                (*stop.inner.try_lock().unwrap()).as_any().type_id() == TypeId::of::<T>()
+            .extract_if(.., |_, stop| {
+                (**stop.inner.get_mut()).as_any().type_id()
+                    == TypeId::of::<BusStopMechContainer<T>>()
+            })
+            .filter_map(|(_, item)| {
+                item.inner
+                    .into_inner()
+                    .to_any()
+                    .downcast::<BusStopMechContainer<T>>()
+                    .unwrap()
+                    .into_inner()
             })
-            .map(|item| *item.inner.into_inner().to_any().downcast().unwrap())
             .collect();
+        let registered_stops = &self.registered_stops;
+        self.stop_policies
+            .retain(|(id, _), _| registered_stops.contains_key(id));
+        self.rebuild_index();
         stop
     }
 
-    /// finds handlers for a specified event (def = TypeId of the tag type on a handler def)
-    fn handlers_for(&mut self, def: TypeId) -> Vec<BusStopContainer> {
+    /// rebuilds the handler index from the registered stops
+    fn rebuild_index(&mut self) {
+        self.handler_index.clear();
+        for (id, stop) in &self.registered_stops {
+            for def in stop.handled() {
+                self.handler_index.insert(*def, *id);
+            }
+        }
+    }
+
+    /// Checks if a registered stop handles `def` (even if it is currently busy)
+    ///
+    /// default values and the dead letter handler (see [`fallback`]) are not taken into account
+    #[must_use]
+    pub fn has_handler<Tag, At, Rt>(&self, def: &'static EventDef<Tag, At, Rt>) -> bool
+    where
+        Tag: unique_type::Unique,
+    {
+        self.handler_index.contains_key(&def.tag_id())
+    }
+
+    /// checks if a call of `def` would be answered by a stop, its default value, or the dead letter handler
+    fn answers(&self, def: TypeId) -> bool {
+        self.handler_index.contains_key(&def)
+            || self.defaults.contains_key(&def)
+            || self.handler_index.contains_key(&DEAD_LETTER.tag_id())
+    }
+
+    /// takes the stop that handles `def` off the bus, if it is not busy, returning if its handler is shared.
+    ///
+    /// a stop that is only being used by shared handlers is not busy for more of them, unless a call to one of its
+    /// other handlers is queued. new shared calls then wait their turn, so that they can not keep the queued call
+    /// waiting forever, but shared calls made (possibly indirectly) by the handlers using the stop still join them
+    fn take_handler(
+        &mut self,
+        tree: &CallTree,
+        caller: Option<FrameId>,
+        def: TypeId,
+    ) -> Option<(Arc<BusStopContainer>, bool)> {
         debug!("Looking for handlers for {:?}", def);
-        self.registered_stops
-            .extract_if(|stop| {
-                if stop.relevant(def) {
-                    trace!("Found match: {:?}", stop.debug());
-                    true
-                } else {
-                    trace!("Mismatch: {:?}", stop.debug());
-                    false
+        let id = *self.handler_index.get(&def)?;
+        if let Some(stop) = self.shared_stops.get(&id) {
+            trace!("Found match: {} (shared)", stop.name());
+            if !stop.is_shared(def) {
+                return None;
+            }
+            let exclusive_queued = tree.queued.iter().any(|call| {
+                self.handler_index.get(&call.def) == Some(&id) && !stop.is_shared(call.def)
+            });
+            let reentrant = caller.is_some_and(|caller| {
+                tree.holders(def)
+                    .any(|holder| tree.waits_on(holder, caller))
+            });
+            if exclusive_queued && !reentrant {
+                debug!(
+                    "a call needing all of {} is queued, not sharing it",
+                    stop.name()
+                );
+                return None;
+            }
+            return Some((stop.clone(), true));
+        }
+        let mut stop = self.registered_stops.remove(&id)?;
+        trace!("Found match: {:?}", stop.debug());
+        let shared = stop.is_shared(def);
+        let stop = Arc::new(stop);
+        if shared {
+            self.shared_stops.insert(id, stop.clone());
+        }
+        Some((stop, shared))
+    }
+
+    /// decides what happens to a call of `def` made by `caller`, taking a stop for it if one is free
+    fn dispatch(&mut self, tree: &CallTree, caller: Option<FrameId>, def: TypeId) -> Dispatch {
+        if self.breaker(def).is_some_and(|breaker| breaker.is_open()) {
+            error!("the circuit breaker for {:?} is open", def);
+            return Dispatch::Fail(BaseFireEventError::CircuitOpen);
+        }
+        if let Some(limited) = self.rate_limited(tree, caller, def) {
+            return limited;
+        }
+        let dispatch = self.find_handler(tree, caller, def);
+        if matches!(dispatch, Dispatch::Start { .. } | Dispatch::Default) {
+            if let Some(limit) = self.limits.get_mut(&def) {
+                limit.take();
+            }
+        }
+        dispatch
+    }
+
+    /// checks if a call of `def` made by `caller` is over the event's rate limit
+    fn rate_limited(
+        &mut self,
+        tree: &CallTree,
+        caller: Option<FrameId>,
+        def: TypeId,
+    ) -> Option<Dispatch> {
+        let limit = self.limits.get_mut(&def)?;
+        let fail = || {
+            error!("{:?} is over its rate limit", def);
+            Some(Dispatch::Fail(BaseFireEventError::RateLimited))
+        };
+        if let Some(max_in_flight) = limit.max_in_flight() {
+            let mut in_flight = tree
+                .frames
+                .iter()
+                .filter(|(_, frame)| frame.def == def && !frame.dead_letter);
+            if in_flight.clone().count() >= max_in_flight {
+                return match (limit.over_limit(), caller) {
+                    (OverLimit::Wait, Some(caller))
+                        if in_flight.any(|(id, _)| !tree.waits_on(*id, caller)) =>
+                    {
+                        debug!("{:?} is at its in flight limit, queueing the call", def);
+                        Some(Dispatch::Queue)
+                    }
+                    _ => fail(),
+                };
+            }
+        }
+        match (limit.wait_time()?, limit.over_limit()) {
+            (wait, OverLimit::Wait) => {
+                debug!("{:?} is over its rate limit, waiting {:?}", def, wait);
+                Some(Dispatch::Wait(wait))
+            }
+            (_, OverLimit::Fail) => fail(),
+        }
+    }
+
+    /// decides which stop handles a call of `def` made by `caller`, taking it if it is free
+    fn find_handler(&mut self, tree: &CallTree, caller: Option<FrameId>, def: TypeId) -> Dispatch {
+        if let Some((stop, shared)) = self.take_handler(tree, caller, def) {
+            return Dispatch::Start {
+                stop,
+                shared,
+                dead_letter: false,
+            };
+        }
+        let mut holders = tree.holders(def).peekable();
+        match (holders.peek().is_some(), caller) {
+            (true, Some(caller)) if !holders.any(|holder| tree.waits_on(holder, caller)) => {
+                debug!("the handler for {:?} is busy, queueing the call", def);
+                Dispatch::Queue
+            }
+            (true, _) => {
+                error!("the handler for {:?} is busy", def);
+                Dispatch::Fail(BaseFireEventError::HandlerBusy)
+            }
+            (false, _) if self.defaults.contains_key(&def) => {
+                info!("no handlers found for {:?}, using its default value", def);
+                Dispatch::Default
+            }
+            (false, _) if def != DEAD_LETTER.tag_id() => {
+                match self.dispatch(tree, caller, DEAD_LETTER.tag_id()) {
+                    Dispatch::Start { stop, shared, .. } => {
+                        info!(
+                            "no handlers found for {:?}, passing it to the dead letter handler",
+                            def
+                        );
+                        Dispatch::Start {
+                            stop,
+                            shared,
+                            dead_letter: true,
+                        }
+                    }
+                    Dispatch::Fail(BaseFireEventError::NoHandler) => {
+                        error!("no handlers found for {:?}", def);
+                        Dispatch::Fail(BaseFireEventError::NoHandler)
+                    }
+                    dispatch => dispatch,
                 }
-            })
-            .collect()
+            }
+            (false, _) => {
+                error!("no handlers found for {:?}", def);
+                Dispatch::Fail(BaseFireEventError::NoHandler)
+            }
+        }
+    }
+
+    /// the span and trace context of a frame, for making nested calls from it
+    fn parent_of(tree: &CallTree, caller: Option<FrameId>) -> (Option<&CallSpan>, SpanContext) {
+        match caller.and_then(|caller| tree.frames.get(&caller)) {
+            Some(frame) => (
+                Some(&frame.span),
+                frame
+                    .local_trace_data
+                    .context
+                    .map_or_else(SpanContext::new_root, |context| context.new_child()),
+            ),
+            None => (None, SpanContext::new_root()),
+        }
     }
 
-    /// generates a new "stack frame" from a given event defeinition
-    fn gen_frame_for(
+    /// generates a new frame, running `handler`'s handler for the event
+    #[allow(clippy::too_many_arguments)]
+    fn gen_frame(
         &mut self,
+        tree: &CallTree,
+        caller: Option<FrameId>,
+        handler: Arc<BusStopContainer>,
+        shared: bool,
+        dead_letter: bool,
         def: TypeId,
         args: DynVar,
         mut local_trace_data: CallEvent,
-    ) -> Result<Frame, CallEvent> {
-        let mut handlers = self.handlers_for(def);
-        assert!(
-            handlers.len() < 2,
-            "currently only supports one handler for an event! this WILL change soonTM"
+        responder: Option<Responder>,
+    ) -> Frame {
+        let retry = if dead_letter {
+            None
+        } else {
+            self.retry_policy(def).and_then(|(policy, clone_args)| {
+                Some(Box::new(Retry {
+                    policy,
+                    args: clone_args(&args)?,
+                    clone_args,
+                    template: local_trace_data.clone(),
+                    previous: vec![],
+                }))
+            })
+        };
+        let (parent_span, context) = Self::parent_of(tree, caller);
+        local_trace_data.begin(context, Some(handler.name()));
+        self.capture_args(&mut local_trace_data, &args);
+        let (handler_def, args) = if dead_letter {
+            let unhandled = UnhandledEvent {
+                name: local_trace_data.handler_name,
+                args_type: local_trace_data.handler_args_t,
+                return_type: local_trace_data.return_t,
+                args,
+            };
+            (DEAD_LETTER.tag_id(), DynVar::new(unhandled))
+        } else {
+            (def, args)
+        };
+        let span = CallSpan::new(parent_span, &local_trace_data, Some(handler.name()));
+        let in_flight = self.call_started(local_trace_data.handler_name, handler.name());
+        let (liveness, interface_recv, handler_fut) = self.run_handler(
+            &handler,
+            handler_def,
+            args,
+            &local_trace_data,
+            &span,
+            Duration::ZERO,
         );
-        if handlers.is_empty() {
-            error!("no handlers found for {:?}", def);
-            local_trace_data.resolve(Resolution::BusError(FireEventError::from(
-                BaseFireEventError::NoHandler,
-            )));
-            return Err(local_trace_data);
+
+        Frame {
+            parent: caller,
+            depth: caller
+                .and_then(|caller| tree.frames.get(&caller))
+                .map_or(0, |frame| frame.depth + 1),
+            liveness,
+            recev_fut: Some(interface_recv.clone().into_recv_async()),
+            interface_recv,
+            handler,
+            shared,
+            handler_fut,
+            def,
+            dead_letter,
+            retry,
+            responder,
+            local_trace_data,
+            span,
+            started: Instant::now(),
+            in_flight,
         }
+    }
 
-        let handler = handlers.remove(0);
+    /// runs `handler` for the call `event` after waiting for `delay`, returning the liveness token and interface receiver
+    /// of its frame along with the handler's future
+    fn run_handler(
+        &self,
+        handler: &Arc<BusStopContainer>,
+        def: TypeId,
+        args: DynVar,
+        event: &CallEvent,
+        span: &CallSpan,
+        delay: Duration,
+    ) -> (
+        Arc<()>,
+        Receiver<BusInterfaceEvent>,
+        BoxFuture<'static, HandlerOutput>,
+    ) {
         let (interface_send, interface_recv): (Sender<BusInterfaceEvent>, _) = flume::bounded(1);
-        let interface = BusInterface::new(interface_send);
-
-        let recev_fut = interface_recv.clone().into_recv_async();
-        let shared_handler = Arc::new(handler);
-        let handler_fut = unsafe {
-            shared_handler
-                .clone()
-                .handle_raw_event(def, args, interface)
+        let liveness = Arc::new(());
+        let info = CallInfo {
+            event: event.clone(),
+            resources: self.resources.clone(),
+            spawner: self.spawner.clone(),
+        };
+        let context = event.context.unwrap_or_else(SpanContext::new_root);
+        let interface = BusInterface::new(interface_send, context, Arc::downgrade(&liveness), info);
+        let handler_fut = if handler.is_shared(def) {
+            unsafe { handler.clone().handle_shared_event(def, args, interface) }.boxed()
+        } else {
+            unsafe { handler.clone().handle_raw_event(def, args, interface) }.boxed()
         };
+        let handler_fut = if delay.is_zero() {
+            handler_fut.boxed()
+        } else {
+            self.sleep(delay).then(|()| handler_fut).boxed()
+        };
+        (liveness, interface_recv, span.instrument(handler_fut))
+    }
 
-        let frame = Frame::ReadyToPoll {
+    /// records a failed attempt at a call, and runs the next one in its place (keeping the stop busy while it waits)
+    fn retry_frame(
+        &mut self,
+        tree: &CallTree,
+        frame: Frame,
+        mut retry: Box<Retry>,
+        args: DynVar,
+        resolution: Resolution,
+    ) -> Frame {
+        let Frame {
+            parent,
+            depth,
+            liveness,
             interface_recv,
             recev_fut,
-            handler: shared_handler,
-            handler_fut: Box::pin(async move { handler_fut.await }),
+            handler,
+            shared,
+            handler_fut,
+            def,
+            dead_letter,
+            retry: _,
+            responder,
+            mut local_trace_data,
+            span,
+            started,
+            in_flight,
+        } = frame;
+        drop(handler_fut);
+        drop(liveness);
+        drop(recev_fut);
+        drop(interface_recv);
+
+        self.finish_call(
+            &local_trace_data,
+            handler.name(),
+            &span,
+            started,
+            &resolution,
+        );
+        drop(in_flight);
+        local_trace_data.resolve(resolution);
+        let attempt = local_trace_data.attempt + 1;
+        retry.previous.push(local_trace_data);
+        warn!(
+            "Retrying {} (retry {} of {})",
+            retry.template.handler_name,
+            attempt,
+            retry.policy.retries()
+        );
+
+        let mut local_trace_data = retry.template.clone();
+        local_trace_data.attempt = attempt;
+        let (parent_span, context) = Self::parent_of(tree, parent);
+        local_trace_data.begin(context, Some(handler.name()));
+        self.capture_args(&mut local_trace_data, &args);
+        let span = CallSpan::new(parent_span, &local_trace_data, Some(handler.name()));
+        let in_flight = self.call_started(local_trace_data.handler_name, handler.name());
+        let delay = retry.policy.backoff().delay(attempt);
+        let (liveness, interface_recv, handler_fut) =
+            self.run_handler(&handler, def, args, &local_trace_data, &span, delay);
+
+        Frame {
+            parent,
+            depth,
+            liveness,
+            recev_fut: Some(interface_recv.clone().into_recv_async()),
+            interface_recv,
+            handler,
+            shared,
+            handler_fut,
+            def,
+            dead_letter,
+            retry: Some(retry),
+            responder,
             local_trace_data,
+            span,
+            // includes the backoff, like the latency seen by the caller
+            started: Instant::now(),
+            in_flight,
+        }
+    }
+
+    /// handles the result of an attempt at a call, either retrying it or finishing the call
+    fn attempt_finished(
+        &mut self,
+        tree: &mut CallTree,
+        mut frame: Frame,
+        handler_return: Option<DynVar>,
+        resolution: Resolution,
+    ) -> Option<(Option<DynVar>, CallEvent)> {
+        if !frame.dead_letter {
+            if let Some(breaker) = self.breaker(frame.def) {
+                breaker.record(&resolution);
+            }
+        }
+        let attempt = frame.local_trace_data.attempt;
+        let next_args = frame
+            .retry
+            .as_ref()
+            .filter(|retry| retry.policy.should_retry(&resolution, attempt))
+            .filter(|_| {
+                !self
+                    .breaker(frame.def)
+                    .is_some_and(|breaker| breaker.is_open())
+            })
+            .and_then(|retry| (retry.clone_args)(&retry.args));
+        match (next_args, frame.retry.take()) {
+            (Some(args), Some(retry)) => {
+                let frame = self.retry_frame(tree, frame, retry, args, resolution);
+                tree.insert(frame);
+                None
+            }
+            (_, retry) => {
+                frame.retry = retry;
+                self.finish_frame(tree, frame, handler_return, resolution)
+            }
+        }
+    }
+
+    /// records a call that failed before reaching a stop
+    fn fail_call(
+        &mut self,
+        tree: &CallTree,
+        caller: Option<FrameId>,
+        args: &DynVar,
+        mut local_trace_data: CallEvent,
+        err: BaseFireEventError,
+    ) -> CallEvent {
+        let (parent_span, context) = Self::parent_of(tree, caller);
+        let resolution = Resolution::BusError(FireEventError::from(err));
+        local_trace_data.begin(context, None);
+        self.capture_args(&mut local_trace_data, args);
+        let span = CallSpan::new(parent_span, &local_trace_data, None);
+        let in_flight = self.call_started(local_trace_data.handler_name, metrics::NO_STOP);
+        self.finish_call(
+            &local_trace_data,
+            metrics::NO_STOP,
+            &span,
+            Instant::now(),
+            &resolution,
+        );
+        drop(in_flight);
+        local_trace_data.resolve(resolution);
+        local_trace_data
+    }
+
+    /// records a call answered with the default value of its event
+    fn default_call(
+        &mut self,
+        tree: &CallTree,
+        caller: Option<FrameId>,
+        def: TypeId,
+        args: &DynVar,
+        mut local_trace_data: CallEvent,
+    ) -> (DynVar, CallEvent) {
+        let value = self.defaults[&def]();
+        let (parent_span, context) = Self::parent_of(tree, caller);
+        local_trace_data.begin(context, None);
+        self.capture_args(&mut local_trace_data, args);
+        let span = CallSpan::new(parent_span, &local_trace_data, None);
+        let in_flight = self.call_started(local_trace_data.handler_name, metrics::NO_STOP);
+        self.finish_call(
+            &local_trace_data,
+            metrics::NO_STOP,
+            &span,
+            Instant::now(),
+            &Resolution::Defaulted,
+        );
+        drop(in_flight);
+        local_trace_data.resolve(Resolution::Defaulted);
+        self.capture_return(&mut local_trace_data, &value);
+        (value, local_trace_data)
+    }
+
+    /// starts (or queues) a nested call made by `caller`
+    fn fire_nested(
+        &mut self,
+        tree: &mut CallTree,
+        caller: FrameId,
+        def: TypeId,
+        args: DynVar,
+        trace_data: CallEvent,
+        responder: Responder,
+    ) {
+        let dispatch = match self
+            .limit_exceeded(tree, caller)
+            .or_else(|| deadline_passed(&trace_data))
+        {
+            Some(err) => Dispatch::Fail(err),
+            None => self.dispatch(tree, Some(caller), def),
         };
+        match dispatch {
+            Dispatch::Start {
+                stop,
+                shared,
+                dead_letter,
+            } => {
+                tree.nested_calls += 1;
+                let frame = self.gen_frame(
+                    tree,
+                    Some(caller),
+                    stop,
+                    shared,
+                    dead_letter,
+                    def,
+                    args,
+                    trace_data,
+                    Some(responder),
+                );
+                tree.insert(frame);
+            }
+            Dispatch::Default => {
+                let (value, local_trace_data) =
+                    self.default_call(tree, Some(caller), def, &args, trace_data);
+                if let Some(caller) = tree.frames.get_mut(&caller) {
+                    caller.local_trace_data.push_inner(local_trace_data);
+                }
+                respond(&responder, Ok(value));
+            }
+            Dispatch::Queue => tree.queued.push(QueuedCall {
+                caller,
+                def,
+                args,
+                responder,
+                trace_data,
+                not_before: None,
+            }),
+            Dispatch::Wait(wait) => {
+                let not_before = Instant::now() + wait;
+                self.schedule_wakeup(tree, not_before);
+                tree.queued.push(QueuedCall {
+                    caller,
+                    def,
+                    args,
+                    responder,
+                    trace_data,
+                    not_before: Some(not_before),
+                });
+            }
+            Dispatch::Fail(err) => {
+                let error_trace = self.fail_call(tree, Some(caller), &args, trace_data, err);
+                respond(
+                    &responder,
+                    Err(CallTrace {
+                        root: Some(error_trace),
+                    }),
+                );
+            }
+        }
+    }
+
+    /// checks if a nested call made by `caller` would go past the depth or call budget limits
+    fn limit_exceeded(&self, tree: &CallTree, caller: FrameId) -> Option<BaseFireEventError> {
+        let depth = tree.frames.get(&caller).map_or(0, |frame| frame.depth) + 1;
+        match (self.max_depth, self.call_budget) {
+            (Some(limit), _) if depth > limit => {
+                error!("Nested call depth limit ({}) exceeded", limit);
+                Some(BaseFireEventError::DepthExceeded { limit })
+            }
+            (_, Some(limit)) if tree.nested_calls >= limit => {
+                error!("Nested call budget ({}) exceeded", limit);
+                Some(BaseFireEventError::BudgetExceeded { limit })
+            }
+            _ => None,
+        }
+    }
 
-        Ok(frame)
+    /// makes sure the queued calls are run again at `not_before`, unless a wakeup is already due by then
+    fn schedule_wakeup(&self, tree: &mut CallTree, not_before: Instant) {
+        if !tree.wakeups.iter().any(|(due, _)| *due <= not_before) {
+            let wait = not_before.saturating_duration_since(Instant::now());
+            tree.wakeups.push((not_before, self.sleep(wait)));
+        }
+    }
+
+    /// retries queued calls, after a stop has been returned or a rate limit wait is over
+    fn run_queued(&mut self, tree: &mut CallTree) {
+        let now = Instant::now();
+        for call in std::mem::take(&mut tree.queued) {
+            if let Some(not_before) = call.not_before.filter(|not_before| *not_before > now) {
+                // the sleeper may have woken up early
+                self.schedule_wakeup(tree, not_before);
+                tree.queued.push(call);
+            } else if tree.frames.contains_key(&call.caller) {
+                self.fire_nested(
+                    tree,
+                    call.caller,
+                    call.def,
+                    call.args,
+                    call.trace_data,
+                    call.responder,
+                );
+            } else {
+                // the caller returned without waiting for the call
+                respond(
+                    &call.responder,
+                    Err(CallTrace::bus_error(
+                        call.trace_data,
+                        BaseFireEventError::InterfaceExpired,
+                    )),
+                );
+            }
+        }
+    }
+
+    /// resolves a frame whose handler has finished, and hands the result to whoever made the call.
+    ///
+    /// for the top level call, the result is returned instead
+    fn finish_frame(
+        &mut self,
+        tree: &mut CallTree,
+        frame: Frame,
+        handler_return: Option<DynVar>,
+        resolution: Resolution,
+    ) -> Option<(Option<DynVar>, CallEvent)> {
+        let Frame {
+            parent,
+            depth: _,
+            liveness,
+            def: _,
+            dead_letter: _,
+            retry,
+            interface_recv,
+            recev_fut,
+            handler,
+            shared,
+            handler_fut,
+            responder,
+            mut local_trace_data,
+            span,
+            started,
+            in_flight,
+        } = frame;
+        drop(handler_fut);
+        drop(liveness);
+        drop(recev_fut);
+        drop(interface_recv);
+
+        let stop = handler.name();
+        let (handler_return, resolution) = match self.return_stop(handler, shared) {
+            Ok(()) => (handler_return, resolution),
+            Err(err) => (None, Resolution::BusError(err)),
+        };
+        self.finish_call(&local_trace_data, stop, &span, started, &resolution);
+        drop(in_flight);
+        local_trace_data.resolve(resolution);
+        if let Some(retry) = retry {
+            local_trace_data.failed_attempts = retry.previous;
+        }
+        if let Some(handler_return) = &handler_return {
+            self.capture_return(&mut local_trace_data, handler_return);
+        }
+        self.run_queued(tree);
+
+        let Some(responder) = responder else {
+            return Some((handler_return, local_trace_data));
+        };
+        match handler_return {
+            Some(handler_return) => {
+                match parent.and_then(|parent| tree.frames.get_mut(&parent)) {
+                    Some(caller) => caller.local_trace_data.push_inner(local_trace_data),
+                    None => debug!("The caller of a nested call returned before it finished"),
+                }
+                respond(&responder, Ok(handler_return));
+            }
+            None => respond(
+                &responder,
+                Err(CallTrace {
+                    root: Some(local_trace_data),
+                }),
+            ),
+        }
+        None
+    }
+
+    /// puts a stop back on the bus once its handler has finished (for shared handlers, once all of them have)
+    fn return_stop(
+        &mut self,
+        handler: Arc<BusStopContainer>,
+        shared: bool,
+    ) -> Result<(), FireEventError> {
+        let handler = if shared {
+            let id = handler.id();
+            drop(handler);
+            match self.shared_stops.get(&id) {
+                Some(stop) if Arc::strong_count(stop) == 1 => {
+                    self.shared_stops.remove(&id).unwrap()
+                }
+                _ => return Ok(()),
+            }
+        } else {
+            handler
+        };
+        match Arc::try_unwrap(handler) {
+            Ok(handler) => {
+                self.registered_stops.insert(handler.id(), handler);
+                Ok(())
+            }
+            Err(handler) => {
+                error!(
+                    "Stop {} is still in use after its handler finished, and has been lost",
+                    handler.name()
+                );
+                Err(FireEventError::from(BaseFireEventError::RuntimeInvariant(
+                    "stop still in use after its handler finished",
+                )))
+            }
+        }
+    }
+
+    /// records structured arguments in the trace (with the `backtrace_serde` feature)
+    #[cfg(feature = "backtrace_serde")]
+    fn capture_args(&self, local_trace_data: &mut CallEvent, args: &DynVar) {
+        local_trace_data.args_value = Some(self.capture_value(args));
+    }
+
+    #[cfg(feature = "backtrace_serde")]
+    fn capture_value(&self, value: &DynVar) -> capture::Captured {
+        let serialize = self.captured_types.get(&value.inner_type_id());
+        capture::Captured::capture(value, self.capture_limit, serialize)
+    }
+
+    #[cfg(not(feature = "backtrace_serde"))]
+    #[inline(always)]
+    #[allow(clippy::unused_self)]
+    fn capture_args(&self, _: &mut CallEvent, _: &DynVar) {}
+
+    /// records a return value in the trace
+    #[allow(clippy::unused_self)]
+    fn capture_return(&self, local_trace_data: &mut CallEvent, return_v: &DynVar) {
+        local_trace_data.set_return(return_v);
+        #[cfg(feature = "backtrace_serde")]
+        {
+            local_trace_data.return_value = Some(self.capture_value(return_v));
+        }
+    }
+
+    /// records the resolution of a call in its span and the bus metrics
+    fn finish_call(
+        &mut self,
+        local_trace_data: &CallEvent,
+        stop: &'static str,
+        span: &CallSpan,
+        started: Instant,
+        resolution: &Resolution,
+    ) {
+        span.record_resolution(resolution);
+        if let Some(metrics) = &self.metrics {
+            metrics::lock(metrics).call_finished(
+                local_trace_data.handler_name,
+                stop,
+                resolution,
+                started.elapsed(),
+            );
+        }
+    }
+
+    /// counts a call in the bus metrics, as in flight until the returned guard is dropped
+    fn call_started(&mut self, event: &'static str, stop: &'static str) -> InFlight {
+        let metrics = self.metrics.get_or_insert_with(Arc::default);
+        InFlight::start(metrics, event, stop)
     }
 
     /// the type-erased function that actually runs an event
     /// ## You probably want to use `DABus::fire`, not this
     /// this function is only made available to allow for defered events (may or may
     /// not become part of this crate in the future, currently used in hayselnut)
-    #[allow(clippy::too_many_lines)] // deal
     pub async fn raw_fire(
+        &mut self,
+        def: TypeId,
+        args: DynVar,
+        trace: CallTrace,
+    ) -> (Option<DynVar>, CallTrace) {
+        let (ret, trace) = self.execute(def, args, trace).await;
+        if let Some(exporter) = &mut self.exporter {
+            exporter.export(&trace);
+        }
+        (ret, trace)
+    }
+
+    /// turns the `Option<DynVar>` returned by the dead letter handler into the return value of the event
+    fn unwrap_dead_letter(handler_output: HandlerOutput) -> HandlerOutput {
+        match handler_output {
+            HandlerOutput::Return(value) => match value.try_to::<Option<DynVar>>() {
+                Ok(Some(value)) => HandlerOutput::Return(value),
+                Ok(None) => HandlerOutput::BusError(BaseFireEventError::NoHandler.into()),
+                Err(..) => HandlerOutput::BusError(
+                    BaseFireEventError::RuntimeInvariant(
+                        "the dead letter handler returned the wrong type",
+                    )
+                    .into(),
+                ),
+            },
+            handler_output => handler_output,
+        }
+    }
+
+    /// runs a top level call, and all of the nested calls it makes
+    async fn execute(
         &mut self,
         def: TypeId,
         args: DynVar,
         mut trace: CallTrace,
     ) -> (Option<DynVar>, CallTrace) {
-        let mut stack: Vec<Frame> = vec![];
-
-        stack.push(
-            match self.gen_frame_for(def, args, trace.take_root().unwrap()) {
-                Ok(initial_frame) => initial_frame,
-                Err(initial_frame_error) => {
-                    trace.set_root(initial_frame_error);
-                    return (None, trace);
+        let mut tree = CallTree::default();
+        let mut root = trace.take_root().unwrap();
+        if root.deadline.is_none() {
+            root.deadline = self.timeout.map(|timeout| SystemTime::now() + timeout);
+        }
+        let missing = if self.strict {
+            self.verify().err()
+        } else {
+            None
+        };
+        let dispatch = match missing {
+            Some(missing) => {
+                error!("Refusing to run event in strict mode: {}", missing);
+                Dispatch::Fail(BaseFireEventError::MissingHandlers(missing))
+            }
+            None => loop {
+                if let Some(err) = deadline_passed(&root) {
+                    break Dispatch::Fail(err);
+                }
+                match self.dispatch(&tree, None, def) {
+                    Dispatch::Wait(wait) => self.sleep(wait).await,
+                    dispatch => break dispatch,
                 }
             },
-        );
+        };
+        match dispatch {
+            Dispatch::Start {
+                stop,
+                shared,
+                dead_letter,
+            } => {
+                let frame = self.gen_frame(
+                    &tree,
+                    None,
+                    stop,
+                    shared,
+                    dead_letter,
+                    def,
+                    args,
+                    root,
+                    None,
+                );
+                tree.insert(frame);
+            }
+            Dispatch::Default => {
+                let (value, root) = self.default_call(&tree, None, def, &args, root);
+                trace.set_root(root);
+                return (Some(value), trace);
+            }
+            Dispatch::Fail(err) => {
+                trace.set_root(self.fail_call(&tree, None, &args, root, err));
+                return (None, trace);
+            }
+            Dispatch::Queue | Dispatch::Wait(..) => unreachable!(),
+        }
 
-        'main: loop {
-            match stack.pop().unwrap() {
-                Frame::ReadyToPoll {
-                    interface_recv,
-                    recev_fut,
-                    handler,
-                    handler_fut,
-                    mut local_trace_data,
-                } => {
-                    let recv_and_handler_fut = OneOf::new(recev_fut, handler_fut);
-                    match recv_and_handler_fut.await {
-                        OneOfResult::F0(interface_event, handler_fut) => {
-                            info!("Received interface event: {:?}", interface_event);
-                            match interface_event.unwrap() {
-                                BusInterfaceEvent::Fire {
-                                    def,
-                                    args,
-                                    responder,
-                                    trace_data: next_event_trace_data,
-                                } => match self.gen_frame_for(def, args, next_event_trace_data) {
-                                    Ok(next_frame) => {
-                                        stack.push(Frame::AwaitingNestedCall {
-                                            interface_recv,
-                                            handler,
-                                            handler_fut,
-                                            responder,
-                                            local_trace_data,
-                                        });
-                                        stack.push(next_frame);
-                                    }
-                                    Err(error_trace) => {
-                                        responder
-                                            .send(Err(CallTrace {
-                                                root: Some(error_trace),
-                                            }))
-                                            .unwrap();
-                                        let recev_fut = interface_recv.clone().into_recv_async();
-                                        stack.push(Frame::ReadyToPoll {
-                                            interface_recv,
-                                            recev_fut,
-                                            handler,
-                                            handler_fut,
-                                            local_trace_data,
-                                        });
-                                    }
-                                },
-                                BusInterfaceEvent::FwdBusError { mut error, blocker } => {
-                                    drop(handler_fut);
-                                    drop(blocker);
-                                    let h = Arc::try_unwrap(handler).unwrap();
-                                    self.registered_stops.push(h);
-                                    local_trace_data.resolve(Resolution::NestedCallError);
-                                    local_trace_data.push_inner(error.take_root().unwrap());
-                                    if stack.is_empty() {
-                                        trace.set_root(local_trace_data);
-                                        break 'main (None, trace);
-                                    } else if let Frame::AwaitingNestedCall {
-                                        interface_recv,
-                                        handler: nested_handler,
-                                        handler_fut: nested_handler_fut,
-                                        responder,
-                                        local_trace_data: caller_handler_trace_data,
-                                    } = stack.pop().unwrap()
-                                    {
-                                        responder
-                                            .send(Err(CallTrace {
-                                                root: Some(local_trace_data),
-                                            }))
-                                            .unwrap();
-                                        let recev_fut = interface_recv.clone().into_recv_async();
-                                        stack.push(Frame::ReadyToPoll {
-                                            interface_recv,
-                                            handler: nested_handler,
-                                            recev_fut,
-                                            handler_fut: nested_handler_fut,
-                                            local_trace_data: caller_handler_trace_data,
-                                        });
-                                    } else {
-                                        unreachable!()
-                                    }
-                                }
+        // runs untill every frame has finished, even after the top level call has returned,
+        // so that no stops are lost
+        let mut result = None;
+        while !tree.frames.is_empty() {
+            match future::poll_fn(|cx| tree.poll(cx)).await {
+                FrameEvent::Wakeup => self.run_queued(&mut tree),
+                FrameEvent::Interface(id, interface_event) => {
+                    info!("Received interface event: {:?}", interface_event);
+                    match interface_event {
+                        BusInterfaceEvent::Fire {
+                            def,
+                            optional: true,
+                            responder,
+                            ..
+                        } if !self.answers(def) => {
+                            debug!("No handler for optional event {:?}", def);
+                            if responder.send(Ok(None)).is_err() {
+                                warn!("The caller of a nested call stopped waiting for its result");
+                            }
+                        }
+                        BusInterfaceEvent::Fire {
+                            def,
+                            args,
+                            responder,
+                            trace_data,
+                            ..
+                        } => self.fire_nested(&mut tree, id, def, args, trace_data, responder),
+                        BusInterfaceEvent::FwdBusError { mut error, blocker } => {
+                            let mut frame = tree.frames.remove(&id).unwrap();
+                            if let Some(root) = error.take_root() {
+                                frame.local_trace_data.push_inner(root);
                             }
+                            if let Some(finished) = self.attempt_finished(
+                                &mut tree,
+                                frame,
+                                None,
+                                Resolution::NestedCallError,
+                            ) {
+                                result = Some(finished);
+                            }
+                            drop(blocker);
+                        }
+                    }
+                }
+                FrameEvent::Returned(id, handler_output) => {
+                    info!("Handler returned");
+                    let mut frame = tree.frames.remove(&id).unwrap();
+                    let handler_output = if frame.dead_letter {
+                        Self::unwrap_dead_letter(handler_output)
+                    } else {
+                        handler_output
+                    };
+                    let (handler_return, resolution) = match handler_output {
+                        HandlerOutput::Return(value) => (Some(value), Resolution::Success),
+                        HandlerOutput::Failed(value, err) => {
+                            warn!("Handler failed: {}", err);
+                            (Some(value), Resolution::HandlerError(err))
                         }
-                        OneOfResult::F1(_, handler_return) => {
-                            info!("Handler returned");
-                            self.registered_stops
-                                .push(Arc::try_unwrap(handler).unwrap());
-                            if stack.is_empty() {
-                                local_trace_data.resolve(Resolution::Success);
-                                local_trace_data.set_return(&handler_return);
-                                trace.set_root(local_trace_data);
-                                break 'main (Some(handler_return), trace);
-                            } else if let Frame::AwaitingNestedCall {
-                                interface_recv,
-                                handler: returned_handler,
-                                handler_fut,
-                                responder,
-                                local_trace_data: mut caller_handler_trace_data,
-                            } = stack.pop().unwrap()
-                            {
-                                local_trace_data.resolve(Resolution::Success);
-                                local_trace_data.set_return(&handler_return);
-                                caller_handler_trace_data.inner.push(local_trace_data);
-                                responder.send(Ok(handler_return)).unwrap();
-                                let recev_fut = interface_recv.clone().into_recv_async();
-                                stack.push(Frame::ReadyToPoll {
-                                    interface_recv,
-                                    handler: returned_handler,
-                                    recev_fut,
-                                    handler_fut,
-                                    local_trace_data: caller_handler_trace_data,
-                                });
-                            } else {
-                                unreachable!()
+                        HandlerOutput::Forward(mut error) => {
+                            if let Some(root) = error.take_root() {
+                                frame.local_trace_data.push_inner(root);
                             }
+                            (None, Resolution::NestedCallError)
+                        }
+                        HandlerOutput::BusError(err) => {
+                            error!("Handler could not be run: {}", err.base());
+                            (None, Resolution::BusError(err))
                         }
-                        OneOfResult::All(..) => unreachable!(),
+                    };
+                    if let Some(finished) =
+                        self.attempt_finished(&mut tree, frame, handler_return, resolution)
+                    {
+                        result = Some(finished);
                     }
                 }
-                Frame::AwaitingNestedCall { .. } => unreachable!(),
             }
         }
+
+        let (ret, root) = result.unwrap();
+        trace.set_root(root);
+        (ret, trace)
     }
 
     /// Fires an event on the bus, running appropreate handlers and returning the result.
@@ -329,13 +1485,16 @@ impl DABus {
     ///
     /// on failure, this returns only the call trace, which can be used to find what went wrong
     ///
+    /// a [fallible handler](EventRegister::fallible_handler) returning `Err` still counts as returning a value,
+    /// but its trace will be resolved with [`Resolution::HandlerError`]
+    ///
     /// # Panics
     ///
-    /// if a handler that is called panics (or the runtime is broken)
+    /// if a handler that is called panics
     ///
     /// # Errors
     ///
-    /// if there is some (expected) error with the runtime. currently this only includes not finding an appropreate handler
+    /// if the call fails, see [`BaseFireEventError`] for the errors reported by the runtime itself
     ///
     pub async fn fire<Tag, At, Rt>(
         &mut self,
@@ -347,21 +1506,111 @@ impl DABus {
         At: DynDebug + Sync + Send + 'static,
         Rt: DynDebug + Sync + Send + 'static,
     {
-        info!("Firing initial event: {:?}", def.name);
-        let trace = CallTrace {
-            root: Some(CallEvent::from_event_def(def, &args)),
-        };
+        let root = CallEvent::from_event_def(def, &args);
+        info!("Firing initial event: {:?}", root.handler_name);
+        let trace = CallTrace { root: Some(root) };
         let _ = def;
         let def = TypeId::of::<Tag>();
         let args = DynVar::new(args);
         match self.raw_fire(def, args, trace).await {
-            (Some(return_v), trace) => Ok(FireEvent {
-                value: return_v.try_to().unwrap(),
-                trace,
-            }),
+            (Some(return_v), mut trace) => match return_v.try_to() {
+                Ok(value) => Ok(FireEvent { value, trace }),
+                Err(return_v) => Err(CallTrace::bus_error(
+                    trace.take_root().unwrap(),
+                    BaseFireEventError::TypeMismatch {
+                        expected: type_name::<Rt>(),
+                        found: return_v.type_name(),
+                    },
+                )),
+            },
             (None, trace) => Err(trace),
         }
     }
+
+    /// Fires an event from outside of any async runtime, blocking the current thread until the call finishes,
+    /// like [`DABus::fire`]
+    ///
+    /// async handlers are run on the current thread, so handlers that rely on a particular runtime (for its timers or IO)
+    /// will not work. the same goes for the [`Sleeper`] and [`Spawner`]s set on the bus
+    ///
+    /// # Panics
+    ///
+    /// if a handler that is called panics
+    ///
+    /// # Errors
+    ///
+    /// see [`DABus::fire`]
+    // returns the same error as `fire`, which clippy only complains about here
+    #[allow(clippy::result_large_err)]
+    pub fn fire_blocking<Tag, At, Rt>(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        args: At,
+    ) -> Result<FireEvent<Rt>, CallTrace>
+    where
+        Tag: unique_type::Unique,
+        At: DynDebug + Sync + Send + 'static,
+        Rt: DynDebug + Sync + Send + 'static,
+    {
+        futures::executor::block_on(self.fire(def, args))
+    }
+
+    /// Fires an event if a stop handles it, like [`DABus::fire`]
+    ///
+    /// # Returns
+    ///
+    /// `Ok(None)` if no stop handles the event (see [`DABus::has_handler`]), it has no default value, and there is
+    /// no dead letter handler (see [`fallback`]), without counting it as an error. otherwise the event is fired as
+    /// usual, so a dead letter handler that does not answer it still fails the call with
+    /// [`BaseFireEventError::NoHandler`]
+    ///
+    /// # Errors
+    ///
+    /// see [`DABus::fire`]
+    pub async fn try_fire<Tag, At, Rt>(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        args: At,
+    ) -> Result<Option<FireEvent<Rt>>, CallTrace>
+    where
+        Tag: unique_type::Unique,
+        At: DynDebug + Sync + Send + 'static,
+        Rt: DynDebug + Sync + Send + 'static,
+    {
+        if !self.answers(def.tag_id()) {
+            debug!("No handler for optional event {:?}", def.name());
+            return Ok(None);
+        }
+        self.fire(def, args).await.map(Some)
+    }
+}
+
+/// checks if a call is being dispatched after its deadline
+fn deadline_passed(trace_data: &CallEvent) -> Option<BaseFireEventError> {
+    let deadline = trace_data.deadline?;
+    (SystemTime::now() > deadline).then(|| {
+        error!(
+            "{} was not started before its deadline",
+            trace_data.handler_name
+        );
+        BaseFireEventError::DeadlineExceeded
+    })
+}
+
+/// sends the result of a nested call back to the handler that made it
+fn respond(responder: &Responder, result: Result<DynVar, CallTrace>) {
+    if responder.send(result.map(Some)).is_err() {
+        warn!("The caller of a nested call stopped waiting for its result");
+    }
+}
+
+impl Debug for DABus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DABus")
+            .field("registered_stops", &self.registered_stops)
+            .field("metrics", &self.metrics)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Default for DABus {
@@ -370,6 +1619,12 @@ impl Default for DABus {
     }
 }
 
+/// Identifies a stop registered on a [`DABus`], see [`DABus::remove`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StopHandle {
+    id: StopId,
+}
+
 #[derive(Debug, Clone)]
 pub struct FireEvent<T> {
     value: T,
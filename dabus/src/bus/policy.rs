@@ -0,0 +1,430 @@
+//! retrying failed calls, failing fast when a handler keeps failing, and throttling events
+//!
+//! policies are set for an event on the whole bus (with [`DABus::set_retry`] and [`DABus::set_circuit_breaker`]),
+//! or by the stop that handles it (with [`EventRegister::retry`] and [`EventRegister::circuit_breaker`]).
+//! a policy set by the stop takes precedence over one set on the bus
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use dabus::{event, BusInterface, BusStop, DABus, EventRegister, bus::policy::{Backoff, CircuitBreaker, RetryPolicy}};
+//!
+//! event!(FETCH, String, Result<String, std::io::Error>);
+//!
+//! #[derive(Debug)]
+//! struct Fetcher;
+//!
+//! impl Fetcher {
+//!     async fn fetch(&mut self, url: String, _i: BusInterface) -> Result<String, std::io::Error> {
+//!         Ok(url)
+//!     }
+//! }
+//!
+//! impl BusStop for Fetcher {
+//!     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+//!         h.fallible_handler(FETCH, Self::fetch)
+//!             .retry(FETCH, RetryPolicy::new(3).with_backoff(Backoff::Exponential {
+//!                 initial: Duration::from_millis(10),
+//!                 max: Duration::from_secs(1),
+//!             }))
+//!             .circuit_breaker(FETCH, CircuitBreaker::new(5, Duration::from_secs(30)))
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut bus = DABus::new();
+//! // the default sleeper uses a timer thread, this uses tokio's timer instead
+//! bus.set_sleeper(|duration| Box::pin(tokio::time::sleep(duration)) as _);
+//! bus.register(Fetcher);
+//! # }
+//! ```
+//!
+//! every attempt is recorded as its own [`CallEvent`](crate::bus::error::CallEvent), with the failed attempts
+//! kept by the last one (see [`CallEvent::failed_attempts`](crate::bus::error::CallEvent::failed_attempts))
+//!
+//! events can also be throttled with a [`RateLimit`], set with [`DABus::set_rate_limit`]
+//!
+//! [`DABus::set_retry`]: crate::DABus::set_retry
+//! [`DABus::set_rate_limit`]: crate::DABus::set_rate_limit
+//! [`DABus::set_circuit_breaker`]: crate::DABus::set_circuit_breaker
+//! [`EventRegister::retry`]: crate::EventRegister::retry
+//! [`EventRegister::circuit_breaker`]: crate::EventRegister::circuit_breaker
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug, Formatter},
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use flume::{Receiver, RecvTimeoutError, Sender};
+use futures::future::BoxFuture;
+
+use crate::{bus::error::Resolution, core::dyn_var::DynVar};
+
+/// copies the arguments of an event, so that they can be passed to another attempt
+pub(crate) type CloneArgs = fn(&DynVar) -> Option<DynVar>;
+
+/// How long to wait before retrying a failed call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// retry right away
+    None,
+    /// wait the same amount of time before every retry
+    Fixed(Duration),
+    /// wait `initial` before the first retry, and twice as long as the last time (up to `max`) before the rest
+    Exponential { initial: Duration, max: Duration },
+}
+
+impl Backoff {
+    /// the time to wait before retry number `retry` (starting at 1)
+    #[must_use]
+    pub fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            Self::None => Duration::ZERO,
+            Self::Fixed(delay) => delay,
+            Self::Exponential { initial, max } => initial
+                .checked_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+                .map_or(max, |delay| delay.min(max)),
+        }
+    }
+}
+
+/// Retries calls that fail, see the [module docs](self)
+///
+/// the arguments of the event must implement [`Clone`], since each attempt takes them by value
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    retries: u32,
+    backoff: Backoff,
+    retry_on: fn(&Resolution) -> bool,
+}
+
+impl RetryPolicy {
+    /// retries a call up to `retries` times (so it is run at most `retries + 1` times), right away,
+    /// whenever it fails (see [`Resolution::is_error`])
+    #[must_use]
+    pub const fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            backoff: Backoff::None,
+            retry_on: Resolution::is_error,
+        }
+    }
+
+    /// sets how long to wait before each retry. the stop stays busy while waiting
+    #[must_use]
+    pub const fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// only retries calls whose resolution matches `retry_on`
+    ///
+    /// ```rust
+    /// # use dabus::bus::{error::Resolution, policy::RetryPolicy};
+    /// let policy = RetryPolicy::new(3).retry_on(|resolution| matches!(resolution, Resolution::HandlerError(..)));
+    /// ```
+    #[must_use]
+    pub const fn retry_on(mut self, retry_on: fn(&Resolution) -> bool) -> Self {
+        self.retry_on = retry_on;
+        self
+    }
+
+    /// the number of retries allowed
+    #[must_use]
+    pub const fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// the backoff between retries
+    #[must_use]
+    pub const fn backoff(&self) -> Backoff {
+        self.backoff
+    }
+
+    /// checks if a call that ended with `resolution` after `attempt` retries should be retried
+    #[must_use]
+    pub fn should_retry(&self, resolution: &Resolution, attempt: u32) -> bool {
+        attempt < self.retries && (self.retry_on)(resolution)
+    }
+}
+
+impl Debug for RetryPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("retries", &self.retries)
+            .field("backoff", &self.backoff)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Fails calls with [`BaseFireEventError::CircuitOpen`] without running them, after too many failures in a row.
+///
+/// once `cooldown` has passed, calls are let through again. the circuit closes after the first one that succeeds,
+/// and opens again after the first one that fails.
+/// every attempt made by a [`RetryPolicy`] counts, and no retries are made while the circuit is open
+///
+/// [`BaseFireEventError::CircuitOpen`]: crate::bus::error::BaseFireEventError::CircuitOpen
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    threshold: usize,
+    cooldown: Duration,
+    failures: usize,
+    opened: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// opens the circuit for `cooldown` after `threshold` failed calls in a row
+    #[must_use]
+    pub const fn new(threshold: usize, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            failures: 0,
+            opened: None,
+        }
+    }
+
+    /// checks if calls are currently failed without running them
+    #[must_use]
+    pub fn is_open(&self) -> bool {
+        self.opened
+            .is_some_and(|opened| opened.elapsed() < self.cooldown)
+    }
+
+    /// records the result of a call
+    pub(crate) fn record(&mut self, resolution: &Resolution) {
+        if resolution.is_error() {
+            self.failures += 1;
+            if self.failures >= self.threshold {
+                if !self.is_open() {
+                    warn!("Circuit opened after {} failures", self.failures);
+                }
+                self.opened = Some(Instant::now());
+            }
+        } else {
+            self.failures = 0;
+            self.opened = None;
+        }
+    }
+}
+
+/// the policies for calls of a single event
+#[derive(Debug, Default)]
+pub(crate) struct EventPolicy {
+    pub retry: Option<(RetryPolicy, CloneArgs)>,
+    pub breaker: Option<CircuitBreaker>,
+}
+
+/// A policy set with [`EventRegister::retry`] or [`EventRegister::circuit_breaker`]
+///
+/// [`EventRegister::retry`]: crate::EventRegister::retry
+/// [`EventRegister::circuit_breaker`]: crate::EventRegister::circuit_breaker
+#[derive(Debug)]
+pub(crate) enum Policy {
+    Retry(RetryPolicy, CloneArgs),
+    Breaker(CircuitBreaker),
+}
+
+impl EventPolicy {
+    pub fn set(&mut self, policy: Policy) {
+        match policy {
+            Policy::Retry(retry, clone_args) => self.retry = Some((retry, clone_args)),
+            Policy::Breaker(breaker) => self.breaker = Some(breaker),
+        }
+    }
+}
+
+/// What happens to calls made while an event is over its [`RateLimit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverLimit {
+    /// wait until the call is allowed. calls that can never be allowed (such as a handler calling its own event
+    /// while it is at the in flight limit) still fail
+    Wait,
+    /// fail the call with [`BaseFireEventError::RateLimited`]
+    ///
+    /// [`BaseFireEventError::RateLimited`]: crate::bus::error::BaseFireEventError::RateLimited
+    Fail,
+}
+
+/// Limits how often an event is run (with a token bucket), and how many calls of it may run at once.
+///
+/// the limits apply to every call of the event on the bus, nested or not
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use dabus::{event, DABus, bus::policy::{OverLimit, RateLimit}};
+/// event!(WRITE_TO_DISK, Vec<u8>, ());
+///
+/// let mut bus = DABus::new();
+/// // bursts of up to 10 writes, and one more every 100ms after that
+/// bus.set_rate_limit(
+///     WRITE_TO_DISK,
+///     RateLimit::new(OverLimit::Wait)
+///         .with_rate(10, Duration::from_millis(100))
+///         .with_max_in_flight(1),
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    over_limit: OverLimit,
+    /// (burst, time to refill one token)
+    rate: Option<(u32, Duration)>,
+    max_in_flight: Option<usize>,
+    tokens: f64,
+    refilled: Option<Instant>,
+}
+
+impl RateLimit {
+    /// no limits, see [`RateLimit::with_rate`] and [`RateLimit::with_max_in_flight`]
+    #[must_use]
+    pub const fn new(over_limit: OverLimit) -> Self {
+        Self {
+            over_limit,
+            rate: None,
+            max_in_flight: None,
+            tokens: 0.0,
+            refilled: None,
+        }
+    }
+
+    /// allows up to `burst` calls at once, and one more for every `per` that passes (up to `burst` again)
+    #[must_use]
+    pub fn with_rate(mut self, burst: u32, per: Duration) -> Self {
+        self.rate = Some((burst, per));
+        self.tokens = f64::from(burst);
+        self
+    }
+
+    /// allows at most `limit` calls to be running at once
+    ///
+    /// a stop only runs one handler at a time, so this only matters for events with
+    /// [shared handlers](crate::EventRegister::shared_handler)
+    #[must_use]
+    pub const fn with_max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = Some(limit);
+        self
+    }
+
+    #[must_use]
+    pub const fn over_limit(&self) -> OverLimit {
+        self.over_limit
+    }
+
+    #[must_use]
+    pub const fn max_in_flight(&self) -> Option<usize> {
+        self.max_in_flight
+    }
+
+    fn refill(&mut self) {
+        let Some((burst, per)) = self.rate else {
+            return;
+        };
+        let now = Instant::now();
+        if let Some(refilled) = self.refilled {
+            let refill = now.duration_since(refilled).as_secs_f64() / per.as_secs_f64();
+            self.tokens = (self.tokens + refill).min(f64::from(burst));
+        }
+        self.refilled = Some(now);
+    }
+
+    /// how long until a call is allowed by the rate limit, or `None` if it is allowed now
+    pub(crate) fn wait_time(&mut self) -> Option<Duration> {
+        self.refill();
+        let (_, per) = self.rate?;
+        (self.tokens < 1.0).then(|| per.mul_f64(1.0 - self.tokens))
+    }
+
+    /// counts a call against the rate limit
+    pub(crate) fn take(&mut self) {
+        if self.rate.is_some() {
+            self.refill();
+            self.tokens -= 1.0;
+        }
+    }
+}
+
+/// Waits for some time before a call is retried or allowed by a [`RateLimit`], see [`DABus::set_sleeper`]
+///
+/// implemented for closures returning a boxed future, such as `|duration| Box::pin(tokio::time::sleep(duration)) as _`
+///
+/// [`DABus::set_sleeper`]: crate::DABus::set_sleeper
+pub trait Sleeper {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+impl<F> Sleeper for F
+where
+    F: Fn(Duration) -> BoxFuture<'static, ()>,
+{
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self(duration)
+    }
+}
+
+/// The default [`Sleeper`], which works with any executor by waiting on a single timer thread (shared by every bus,
+/// and started the first time it is needed)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadSleeper;
+
+impl Sleeper for ThreadSleeper {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let (wake, woken) = flume::bounded::<()>(1);
+        // if the timer thread could not be started, `wake` is dropped and the sleep ends right away
+        let _ = timer_thread().send((Instant::now() + duration, wake));
+        Box::pin(async move {
+            let _ = woken.recv_async().await;
+        })
+    }
+}
+
+/// a request for the timer thread to wake a sleeper at some point
+type TimerRequest = (Instant, Sender<()>);
+
+/// the timer thread used by [`ThreadSleeper`]
+fn timer_thread() -> &'static Sender<TimerRequest> {
+    static TIMER: OnceLock<Sender<TimerRequest>> = OnceLock::new();
+    TIMER.get_or_init(|| {
+        let (requests, recv) = flume::unbounded();
+        if let Err(err) = std::thread::Builder::new()
+            .name(String::from("dabus-timer"))
+            .spawn(move || run_timer(&recv))
+        {
+            error!(
+                "Could not start the timer thread, sleeps will end early: {}",
+                err
+            );
+        }
+        requests
+    })
+}
+
+fn run_timer(requests: &Receiver<TimerRequest>) {
+    // keyed by a counter as well, for sleeps that end at the same time
+    let mut timers = BTreeMap::<(Instant, u64), Sender<()>>::new();
+    let mut next_id = 0;
+    loop {
+        let now = Instant::now();
+        while let Some(timer) = timers.first_entry() {
+            if timer.key().0 > now {
+                break;
+            }
+            let _ = timer.remove().send(());
+        }
+        let request = match timers.keys().next() {
+            Some((deadline, _)) => match requests.recv_deadline(*deadline) {
+                Ok(request) => request,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return,
+            },
+            None => match requests.recv() {
+                Ok(request) => request,
+                Err(..) => return,
+            },
+        };
+        let (deadline, wake) = request;
+        timers.insert((deadline, next_id), wake);
+        next_id += 1;
+    }
+}
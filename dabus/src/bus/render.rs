@@ -0,0 +1,161 @@
+//! tree rendering of call traces
+//!
+//! ```text
+//! OUTER (()) -> u32 [my_crate::Outer] ::: nested call error
+//! │   args: ()
+//! ├── INNER (u32) -> u32 [my_crate::Inner] ::: success
+//! │       args: 3
+//! │       ret: 6
+//! └── MISSING (alloc::string::String) -> () ::: bus error: No handler matches the event!  <-- source
+//!         args: "hello"
+//! ```
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::bus::error::{CallEvent, CallTrace, Resolution};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+
+/// Renders a [`CallTrace`] as a tree, see [`CallTrace::colored`] and [`CallTrace::plain`]
+///
+/// the failing path (as found by [`CallTrace::source`]) is highlighted, and recorded arguments and
+/// return values (with the `backtrace_track_values` feature) are included under each call
+#[derive(Debug, Clone, Copy)]
+pub struct TraceDisplay<'a> {
+    trace: &'a CallTrace,
+    color: bool,
+}
+
+impl<'a> TraceDisplay<'a> {
+    pub(crate) const fn new(trace: &'a CallTrace, color: bool) -> Self {
+        Self { trace, color }
+    }
+
+    fn paint(&self, f: &mut Formatter<'_>, style: &str, text: impl Display) -> fmt::Result {
+        if self.color {
+            write!(f, "{style}{text}{RESET}")
+        } else {
+            write!(f, "{text}")
+        }
+    }
+
+    fn event(
+        &self,
+        f: &mut Formatter<'_>,
+        event: &CallEvent,
+        prefix: &str,
+        failing_path: &[&CallEvent],
+    ) -> fmt::Result {
+        let on_path = failing_path.iter().any(|e| std::ptr::eq(*e, event));
+        let is_source = failing_path.last().is_some_and(|e| std::ptr::eq(*e, event));
+
+        self.paint(
+            f,
+            if on_path { BOLD } else { "" },
+            format_args!(
+                "{} ({}) -> {}",
+                event.handler_name, event.handler_args_t, event.return_t
+            ),
+        )?;
+        if let Some(stop) = event.stop_name {
+            self.paint(f, DIM, format_args!(" [{stop}]"))?;
+        }
+        if event.attempt > 0 {
+            self.paint(f, DIM, format_args!(" (retry {})", event.attempt))?;
+        }
+        f.write_str(" ::: ")?;
+        match &event.resolution {
+            Some(resolution) => {
+                let style = match resolution {
+                    Resolution::Success => GREEN,
+                    Resolution::NestedCallError | Resolution::Defaulted => YELLOW,
+                    Resolution::BusError(..) | Resolution::HandlerError(..) => RED,
+                };
+                self.paint(f, style, resolution)?;
+            }
+            None => self.paint(f, DIM, "unresolved")?,
+        }
+        if is_source {
+            self.paint(f, RED, "  <-- source")?;
+        }
+        writeln!(f)?;
+
+        let detail_prefix = format!(
+            "{prefix}{}",
+            if event.failed_attempts.is_empty() && event.inner.is_empty() {
+                "  "
+            } else {
+                "│ "
+            }
+        );
+        if let Some(args) = &event.handler_args {
+            self.value(f, &detail_prefix, "args", args)?;
+        }
+        #[cfg(feature = "backtrace_serde")]
+        if let (None, Some(args)) = (&event.handler_args, &event.args_value) {
+            self.value(f, &detail_prefix, "args", &captured(args))?;
+        }
+        if let Some(return_v) = &event.return_v {
+            self.value(f, &detail_prefix, "ret", return_v)?;
+        }
+        #[cfg(feature = "backtrace_serde")]
+        if let (None, Some(return_v)) = (&event.return_v, &event.return_value) {
+            self.value(f, &detail_prefix, "ret", &captured(return_v))?;
+        }
+
+        let children = event.failed_attempts.len() + event.inner.len();
+        let attempts = event.failed_attempts.iter().map(|attempt| (true, attempt));
+        let inner = event.inner.iter().map(|inner| (false, inner));
+        for (i, (failed_attempt, inner)) in attempts.chain(inner).enumerate() {
+            let last = i + 1 == children;
+            f.write_str(prefix)?;
+            self.paint(f, DIM, if last { "└── " } else { "├── " })?;
+            if failed_attempt {
+                self.paint(f, DIM, "failed attempt: ")?;
+            }
+            let inner_prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+            self.event(f, inner, &inner_prefix, failing_path)?;
+        }
+        Ok(())
+    }
+
+    fn value(&self, f: &mut Formatter<'_>, prefix: &str, label: &str, value: &str) -> fmt::Result {
+        for (i, line) in value.lines().enumerate() {
+            f.write_str(prefix)?;
+            if i == 0 {
+                self.paint(f, DIM, format_args!("  {label}: "))?;
+            } else {
+                f.write_str(&" ".repeat(label.len() + 4))?;
+            }
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "backtrace_serde")]
+fn captured(value: &crate::bus::capture::Captured) -> String {
+    use crate::bus::capture::Captured;
+    match value {
+        Captured::Value(value) => value.to_string(),
+        Captured::Truncated(limit) => format!("<larger than {limit} bytes>"),
+        Captured::Unserializable => String::from("<not serializable>"),
+    }
+}
+
+impl Display for TraceDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.trace.root {
+            Some(root) => {
+                let failing_path = self.trace.failing_path().unwrap_or_default();
+                self.event(f, root, "", &failing_path)
+            }
+            None => f.write_str("<empty call trace>\n"),
+        }
+    }
+}
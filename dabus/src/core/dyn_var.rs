@@ -1,4 +1,7 @@
-use std::{any::TypeId, fmt::Debug};
+use std::{
+    any::{Any, TypeId},
+    fmt::Debug,
+};
 
 use crate::util::GeneralRequirements;
 
@@ -28,6 +31,27 @@ impl DynVar {
         (*self.val).type_name()
     }
 
+    /// the type id of the contained value (unlike [`Any::type_id`], which is the id of `DynVar` itself)
+    #[must_use]
+    pub fn inner_type_id(&self) -> TypeId {
+        (*self.val).as_any().type_id()
+    }
+
+    /// debug-formats the contained value (unlike the [`Debug`] impl of `DynVar` itself, which includes the wrapper)
+    #[must_use]
+    pub fn debug_inner(&self) -> &dyn Debug {
+        (*self.val).as_dbg()
+    }
+
+    /// serializes the contained value as JSON, if its type implements `Serialize`
+    #[cfg(feature = "backtrace_serde")]
+    pub fn serialize_json(
+        &self,
+        writer: &mut dyn std::io::Write,
+    ) -> Option<serde_json::Result<()>> {
+        (*self.val).serialize_json(writer)
+    }
+
     #[must_use]
     pub fn as_ref<T: GeneralRequirements>(&self) -> Option<&T> {
         (*self.val).as_any().downcast_ref()
@@ -51,7 +75,7 @@ impl DynVar {
     /// the caller must make sure that the contained value has type T. calling with the incorrect type is *undefined behavior*
     #[must_use]
     pub unsafe fn as_ref_unchecked<T: GeneralRequirements>(&self) -> &T {
-        (*self.val).as_any().downcast_ref_unchecked()
+        downcast_ref_unchecked((*self.val).as_any())
     }
 
     /// # Safety
@@ -59,7 +83,7 @@ impl DynVar {
     /// the caller must make sure that the contained value has type T. calling with the incorrect type is *undefined behavior*
     #[must_use]
     pub unsafe fn as_mut_unchecked<T: GeneralRequirements>(&mut self) -> &mut T {
-        (*self.val).mut_any().downcast_mut_unchecked()
+        downcast_mut_unchecked((*self.val).mut_any())
     }
 
     /// # Safety
@@ -67,7 +91,7 @@ impl DynVar {
     /// the caller must make sure that the contained value has type T. calling with the incorrect type is *undefined behavior*
     #[must_use]
     pub unsafe fn try_to_unchecked<T: GeneralRequirements>(self) -> T {
-        *self.val.to_any().downcast_unchecked()
+        *downcast_unchecked(self.val.to_any())
     }
 
     #[must_use]
@@ -102,3 +126,48 @@ impl Debug for DynVar {
             .finish()
     }
 }
+
+// the unchecked downcasts need the `nightly` feature. otherwise the type is still checked, and a wrong one panics
+// instead of being undefined behavior
+
+#[cfg(not(feature = "nightly"))]
+const WRONG_TYPE: &str = "a value did not have the type it was used as";
+
+/// # Safety
+///
+/// `any` must have type T
+#[cfg(feature = "nightly")]
+pub(crate) unsafe fn downcast_ref_unchecked<T: Any>(any: &dyn Any) -> &T {
+    any.downcast_unchecked_ref()
+}
+
+#[cfg(not(feature = "nightly"))]
+pub(crate) unsafe fn downcast_ref_unchecked<T: Any>(any: &dyn Any) -> &T {
+    any.downcast_ref().expect(WRONG_TYPE)
+}
+
+/// # Safety
+///
+/// `any` must have type T
+#[cfg(feature = "nightly")]
+unsafe fn downcast_mut_unchecked<T: Any>(any: &mut dyn Any) -> &mut T {
+    any.downcast_unchecked_mut()
+}
+
+#[cfg(not(feature = "nightly"))]
+unsafe fn downcast_mut_unchecked<T: Any>(any: &mut dyn Any) -> &mut T {
+    any.downcast_mut().expect(WRONG_TYPE)
+}
+
+/// # Safety
+///
+/// `any` must have type T
+#[cfg(feature = "nightly")]
+unsafe fn downcast_unchecked<T: Any>(any: Box<dyn Any>) -> Box<T> {
+    any.downcast_unchecked()
+}
+
+#[cfg(not(feature = "nightly"))]
+unsafe fn downcast_unchecked<T: Any>(any: Box<dyn Any>) -> Box<T> {
+    any.downcast().expect(WRONG_TYPE)
+}
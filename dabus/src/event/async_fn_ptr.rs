@@ -6,50 +6,173 @@
 //!
 //! no touchie
 
-use crate::{core::dyn_var::DynVar, interface::BusInterface};
+use crate::{
+    bus::error::{BaseFireEventError, CallTrace, FireEventError, HandlerError},
+    core::dyn_var::{self, DynVar},
+    event::extract::FromInterface,
+    interface::BusInterface,
+    util::dyn_debug::DynDebug,
+};
 
 use core::marker::PhantomData;
+use std::{
+    any::Any,
+    error::Error,
+    panic::{self, AssertUnwindSafe},
+};
 
 use futures::future::{BoxFuture, Future};
 
-pub trait AsyncFnPtr<'a, H: 'a, At, Rt> {
+/// a handler, called with the stop, the event's arguments, and values extracted from the [`BusInterface`]
+/// (see [`FromInterface`])
+///
+/// `M` tells apart the shapes handlers can have: taking `&mut self` or `&self`, with or without the event's arguments,
+/// followed by up to four extracted values. it defaults to the usual `(&mut self, At, BusInterface)`
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a handler for an event taking `{At}` and returning `{Rt}`",
+    note = "handlers take `&mut self` or `&self`, then optionally the event's arguments, then up to four values implementing `FromInterface`"
+)]
+pub trait AsyncFnPtr<'a, H: 'a, At, Rt, M = (Mut, WithArgs, (BusInterface,))> {
     type Fut: Future<Output = Rt> + Send + 'a;
-    fn call(self, h: &'a mut H, a: At, i: BusInterface) -> Self::Fut;
+    /// # Errors
+    ///
+    /// if one of the handler's parameters could not be extracted
+    fn call(self, h: &'a mut H, a: At, i: BusInterface) -> Result<Self::Fut, BaseFireEventError>;
 }
 
-impl<'a, H: 'a, At, Fut: Future + Send + 'a, F: FnOnce(&'a mut H, At, BusInterface) -> Fut>
-    AsyncFnPtr<'a, H, At, Fut::Output> for F
-{
-    type Fut = Fut;
-    fn call(self, h: &'a mut H, a: At, i: BusInterface) -> Self::Fut {
-        self(h, a, i)
-    }
+/// a handler taking `&self`, which can run alongside the other shared handlers of its stop
+/// (see [`EventRegister::shared_handler`](crate::EventRegister::shared_handler))
+///
+/// `M` is the same as for [`AsyncFnPtr`], and defaults to `(&self, At, BusInterface)`
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a shared handler for an event taking `{At}` and returning `{Rt}`",
+    note = "shared handlers take `&self`, then optionally the event's arguments, then up to four values implementing `FromInterface`"
+)]
+pub trait SharedAsyncFnPtr<'a, H: 'a, At, Rt, M = (Ref, WithArgs, (BusInterface,))> {
+    type Fut: Future<Output = Rt> + Send + 'a;
+    /// # Errors
+    ///
+    /// if one of the handler's parameters could not be extracted
+    fn call(self, h: &'a H, a: At, i: BusInterface) -> Result<Self::Fut, BaseFireEventError>;
 }
 
+/// marks handlers taking `&mut self`
+#[doc(hidden)]
+pub struct Mut;
+/// marks handlers taking `&self`
+#[doc(hidden)]
+pub struct Ref;
+/// marks handlers taking the event's arguments
+#[doc(hidden)]
+pub struct WithArgs;
+/// marks handlers that leave out the event's arguments
+#[doc(hidden)]
+pub struct NoArgs;
+
+macro_rules! impl_async_fn_ptr {
+    ($($e:ident),*) => {
+        impl_async_fn_ptr!(@impl (Mut, WithArgs), [&'a mut H, At], |h, a| [h, a] $(, $e)*);
+        impl_async_fn_ptr!(@impl (Ref, WithArgs), [&'a H, At], |h, a| [&*h, a] $(, $e)*);
+        impl_async_fn_ptr!(@impl (Mut, NoArgs), [&'a mut H], |h, _a| [h] $(, $e)*);
+        impl_async_fn_ptr!(@impl (Ref, NoArgs), [&'a H], |h, _a| [&*h] $(, $e)*);
+        impl_async_fn_ptr!(@shared WithArgs, [&'a H, At], |h, a| [h, a] $(, $e)*);
+        impl_async_fn_ptr!(@shared NoArgs, [&'a H], |h, _a| [h] $(, $e)*);
+    };
+    (
+        @shared $args:ident, [$($param:ty),+], |$h:ident, $a:ident| [$($input:expr),+]
+        $(, $e:ident)*
+    ) => {
+        #[allow(non_snake_case, unused_variables)]
+        impl<'a, H: 'a, At, Fut, F, $($e),*> SharedAsyncFnPtr<'a, H, At, Fut::Output, (Ref, $args, ($($e,)*))>
+            for F
+        where
+            Fut: Future + Send + 'a,
+            F: FnOnce($($param,)+ $($e),*) -> Fut,
+            $($e: FromInterface,)*
+        {
+            type Fut = Fut;
+            fn call(self, $h: &'a H, $a: At, i: BusInterface) -> Result<Fut, BaseFireEventError> {
+                $(let $e = $e::from_interface(&i)?;)*
+                Ok(self($($input,)+ $($e),*))
+            }
+        }
+    };
+    (
+        @impl ($receiver:ident, $args:ident), [$($param:ty),+], |$h:ident, $a:ident| [$($input:expr),+]
+        $(, $e:ident)*
+    ) => {
+        // extracted values are named after their types, and there may be nothing to extract from the interface
+        #[allow(non_snake_case, unused_variables)]
+        impl<'a, H: 'a, At, Fut, F, $($e),*> AsyncFnPtr<'a, H, At, Fut::Output, ($receiver, $args, ($($e,)*))>
+            for F
+        where
+            Fut: Future + Send + 'a,
+            F: FnOnce($($param,)+ $($e),*) -> Fut,
+            $($e: FromInterface,)*
+        {
+            type Fut = Fut;
+            fn call(self, $h: &'a mut H, $a: At, i: BusInterface) -> Result<Fut, BaseFireEventError> {
+                $(let $e = $e::from_interface(&i)?;)*
+                Ok(self($($input,)+ $($e),*))
+            }
+        }
+    };
+}
+
+impl_async_fn_ptr!();
+impl_async_fn_ptr!(E1);
+impl_async_fn_ptr!(E1, E2);
+impl_async_fn_ptr!(E1, E2, E3);
+impl_async_fn_ptr!(E1, E2, E3, E4);
+
 #[derive(Clone)]
-pub struct HandlerFn<H: 'static, At: 'static, Rt: 'static, P>
+pub struct HandlerFn<H: 'static, At: 'static, Rt: 'static, P, M = (Mut, WithArgs, (BusInterface,))>
 where
-    P: for<'a> AsyncFnPtr<'a, H, At, Rt> + Copy,
+    P: for<'a> AsyncFnPtr<'a, H, At, Rt, M> + Copy,
 {
     f: P,
     _t: PhantomData<&'static (H, At, Rt)>,
+    _m: PhantomData<fn() -> M>,
 }
 
-impl<H: 'static + Send, At: 'static + Send, Rt: 'static, P> HandlerFn<H, At, Rt, P>
+impl<H: 'static + Send, At: 'static + Send, Rt: 'static, P, M> HandlerFn<H, At, Rt, P, M>
 where
-    P: for<'a> AsyncFnPtr<'a, H, At, Rt> + Send + Copy + 'static,
+    P: for<'a> AsyncFnPtr<'a, H, At, Rt, M> + Send + Copy + 'static,
 {
     #[must_use]
     pub const fn new(f: P) -> Self {
-        Self { f, _t: PhantomData }
+        Self {
+            f,
+            _t: PhantomData,
+            _m: PhantomData,
+        }
     }
 
-    pub fn call<'a, 'b>(&'b self, h: &'a mut H, a: At, i: BusInterface) -> BoxFuture<'a, Rt> {
-        let f = self.f;
-        Box::pin(async move { f.call(h, a, i).await })
+    /// # Errors
+    ///
+    /// if one of the handler's parameters could not be extracted
+    pub fn call<'a>(
+        &self,
+        h: &'a mut H,
+        a: At,
+        i: BusInterface,
+    ) -> Result<BoxFuture<'a, Rt>, BaseFireEventError> {
+        Ok(Box::pin(self.f.call(h, a, i)?))
     }
 }
 
+/// what a type-erased handler produced
+pub enum HandlerOutput {
+    /// the handler returned normally
+    Return(DynVar),
+    /// a fallible handler returned `Err`. the value (the whole `Result`) is still passed back to the caller
+    Failed(DynVar, HandlerError),
+    /// a forwarding handler returned the error of a nested call, which is passed on to the caller
+    Forward(Box<CallTrace>),
+    /// the handler could not be run
+    BusError(FireEventError),
+}
+
 pub trait HandlerCallableErased {
     /// # Safety
     ///
@@ -59,15 +182,72 @@ pub trait HandlerCallableErased {
         h: &'a mut DynVar,
         a: DynVar,
         i: BusInterface,
-    ) -> BoxFuture<'a, DynVar>;
+    ) -> BoxFuture<'a, HandlerOutput>;
+}
+
+impl<H, At, Rt, P, M> HandlerCallableErased for HandlerFn<H, At, Rt, P, M>
+where
+    P: for<'a> AsyncFnPtr<'a, H, At, Rt, M> + Send + Sync + Copy + 'static,
+    H: DynDebug + Send + Sync + 'static,
+    At: DynDebug + Send + Sync + 'static,
+    Rt: DynDebug + Send + Sync + 'static,
+{
+    /// # Safety
+    ///
+    /// the caller must guarentee that `h` and `a` have the same type as `H` and `At` on the trait implementation
+    unsafe fn call<'a>(
+        &'a self,
+        h: &'a mut DynVar,
+        a: DynVar,
+        i: BusInterface,
+    ) -> BoxFuture<'a, HandlerOutput> {
+        Box::pin(async move {
+            let h = h.as_mut_unchecked::<H>();
+            let a = a.try_to_unchecked::<At>();
+            match self.call(h, a, i) {
+                Ok(fut) => HandlerOutput::Return(DynVar::new(fut.await)),
+                Err(err) => HandlerOutput::BusError(FireEventError::from(err)),
+            }
+        })
+    }
+}
+
+/// a [`HandlerFn`] returning `Result<T, E>`, where `Err` is reported to the bus as a [`HandlerError`]
+#[derive(Clone)]
+pub struct FallibleHandlerFn<
+    H: 'static,
+    At: 'static,
+    T: 'static,
+    E: 'static,
+    P,
+    M = (Mut, WithArgs, (BusInterface,)),
+> where
+    P: for<'a> AsyncFnPtr<'a, H, At, Result<T, E>, M> + Copy,
+{
+    f: HandlerFn<H, At, Result<T, E>, P, M>,
 }
 
-impl<H, At, Rt, P> HandlerCallableErased for HandlerFn<H, At, Rt, P>
+impl<H: 'static + Send, At: 'static + Send, T: 'static, E: 'static, P, M>
+    FallibleHandlerFn<H, At, T, E, P, M>
 where
-    P: for<'a> AsyncFnPtr<'a, H, At, Rt> + Send + Sync + Copy + 'static,
-    H: Send + Sync + 'static,
-    At: Send + Sync + 'static,
-    Rt: Send + Sync + 'static,
+    P: for<'a> AsyncFnPtr<'a, H, At, Result<T, E>, M> + Send + Copy + 'static,
+{
+    #[must_use]
+    pub const fn new(f: P) -> Self {
+        Self {
+            f: HandlerFn::new(f),
+        }
+    }
+}
+
+impl<H, At, T, E, P, M> HandlerCallableErased for FallibleHandlerFn<H, At, T, E, P, M>
+where
+    P: for<'a> AsyncFnPtr<'a, H, At, Result<T, E>, M> + Send + Sync + Copy + 'static,
+    H: DynDebug + Send + Sync + 'static,
+    At: DynDebug + Send + Sync + 'static,
+    T: Send + Sync + 'static,
+    E: Error + Send + Sync + 'static,
+    Result<T, E>: DynDebug,
 {
     /// # Safety
     ///
@@ -77,12 +257,210 @@ where
         h: &'a mut DynVar,
         a: DynVar,
         i: BusInterface,
-    ) -> BoxFuture<'a, DynVar> {
+    ) -> BoxFuture<'a, HandlerOutput> {
         Box::pin(async move {
             let h = h.as_mut_unchecked::<H>();
             let a = a.try_to_unchecked::<At>();
-            let r = self.call(h, a, i).await;
-            DynVar::new(r)
+            let r = match self.f.call(h, a, i) {
+                Ok(fut) => fut.await,
+                Err(err) => return HandlerOutput::BusError(FireEventError::from(err)),
+            };
+            match &r {
+                Ok(..) => HandlerOutput::Return(DynVar::new(r)),
+                Err(err) => {
+                    let err = HandlerError::new(err);
+                    HandlerOutput::Failed(DynVar::new(r), err)
+                }
+            }
+        })
+    }
+}
+
+/// a [`HandlerFn`] returning `Result<Rt, CallTrace>`, where `Err` is forwarded to the caller
+#[derive(Clone)]
+pub struct ForwardingHandlerFn<
+    H: 'static,
+    At: 'static,
+    Rt: 'static,
+    P,
+    M = (Mut, WithArgs, (BusInterface,)),
+> where
+    P: for<'a> AsyncFnPtr<'a, H, At, Result<Rt, CallTrace>, M> + Copy,
+{
+    f: HandlerFn<H, At, Result<Rt, CallTrace>, P, M>,
+}
+
+impl<H: 'static + Send, At: 'static + Send, Rt: 'static, P, M> ForwardingHandlerFn<H, At, Rt, P, M>
+where
+    P: for<'a> AsyncFnPtr<'a, H, At, Result<Rt, CallTrace>, M> + Send + Copy + 'static,
+{
+    #[must_use]
+    pub const fn new(f: P) -> Self {
+        Self {
+            f: HandlerFn::new(f),
+        }
+    }
+}
+
+impl<H, At, Rt, P, M> HandlerCallableErased for ForwardingHandlerFn<H, At, Rt, P, M>
+where
+    P: for<'a> AsyncFnPtr<'a, H, At, Result<Rt, CallTrace>, M> + Send + Sync + Copy + 'static,
+    H: DynDebug + Send + Sync + 'static,
+    At: DynDebug + Send + Sync + 'static,
+    Rt: DynDebug + Send + Sync + 'static,
+{
+    /// # Safety
+    ///
+    /// the caller must guarentee that `h` and `a` have the same type as `H` and `At` on the trait implementation
+    unsafe fn call<'a>(
+        &'a self,
+        h: &'a mut DynVar,
+        a: DynVar,
+        i: BusInterface,
+    ) -> BoxFuture<'a, HandlerOutput> {
+        Box::pin(async move {
+            let h = h.as_mut_unchecked::<H>();
+            let a = a.try_to_unchecked::<At>();
+            match self.f.call(h, a, i) {
+                Ok(fut) => match fut.await {
+                    Ok(r) => HandlerOutput::Return(DynVar::new(r)),
+                    Err(trace) => HandlerOutput::Forward(Box::new(trace)),
+                },
+                Err(err) => HandlerOutput::BusError(FireEventError::from(err)),
+            }
+        })
+    }
+}
+
+/// a synchronous handler, run through the bus's [`Spawner`](crate::bus::blocking::Spawner)
+#[derive(Clone)]
+pub struct BlockingHandlerFn<H: 'static, At: 'static, Rt: 'static, F>
+where
+    F: Fn(&mut H, At) -> Rt + Copy,
+{
+    f: F,
+    _t: PhantomData<&'static (H, At, Rt)>,
+}
+
+impl<H: 'static, At: 'static, Rt: 'static, F> BlockingHandlerFn<H, At, Rt, F>
+where
+    F: Fn(&mut H, At) -> Rt + Copy,
+{
+    #[must_use]
+    pub const fn new(f: F) -> Self {
+        Self { f, _t: PhantomData }
+    }
+}
+
+impl<H, At, Rt, F> HandlerCallableErased for BlockingHandlerFn<H, At, Rt, F>
+where
+    F: Fn(&mut H, At) -> Rt + Copy + Send + Sync + 'static,
+    H: DynDebug + Send + Sync + 'static,
+    At: DynDebug + Send + Sync + 'static,
+    Rt: DynDebug + Send + Sync + 'static,
+{
+    /// # Safety
+    ///
+    /// the caller must guarentee that `h` and `a` have the same type as `H` and `At` on the trait implementation
+    unsafe fn call<'a>(
+        &'a self,
+        h: &'a mut DynVar,
+        a: DynVar,
+        i: BusInterface,
+    ) -> BoxFuture<'a, HandlerOutput> {
+        Box::pin(async move {
+            // the stop is moved to the blocking thread and put back once the handler is done. if this future is
+            // dropped first, whatever owns `h` is dropped along with it, so the placeholder is never seen
+            let mut stop = std::mem::replace(h, DynVar::new(()));
+            let f = self.f;
+            let (done, result) = flume::bounded(1);
+            i.spawn_blocking(Box::new(move || {
+                let r = panic::catch_unwind(AssertUnwindSafe(|| {
+                    let h = unsafe { stop.as_mut_unchecked::<H>() };
+                    let a = unsafe { a.try_to_unchecked::<At>() };
+                    DynVar::new(f(h, a))
+                }));
+                let _ = done.send((stop, r));
+            }));
+            match result.recv_async().await {
+                Ok((stop, Ok(r))) => {
+                    *h = stop;
+                    HandlerOutput::Return(r)
+                }
+                Ok((_, Err(payload))) => panic::resume_unwind(payload),
+                Err(..) => {
+                    panic!("the bus's spawner dropped a blocking handler without running it")
+                }
+            }
+        })
+    }
+}
+
+/// a handler that only borrows its stop, see [`SharedAsyncFnPtr`]
+#[derive(Clone)]
+pub struct SharedHandlerFn<
+    H: 'static,
+    At: 'static,
+    Rt: 'static,
+    P,
+    M = (Ref, WithArgs, (BusInterface,)),
+> where
+    P: for<'a> SharedAsyncFnPtr<'a, H, At, Rt, M> + Copy,
+{
+    f: P,
+    _t: PhantomData<&'static (H, At, Rt)>,
+    _m: PhantomData<fn() -> M>,
+}
+
+impl<H: 'static, At: 'static, Rt: 'static, P, M> SharedHandlerFn<H, At, Rt, P, M>
+where
+    P: for<'a> SharedAsyncFnPtr<'a, H, At, Rt, M> + Copy,
+{
+    #[must_use]
+    pub const fn new(f: P) -> Self {
+        Self {
+            f,
+            _t: PhantomData,
+            _m: PhantomData,
+        }
+    }
+}
+
+pub trait SharedHandlerCallableErased {
+    /// # Safety
+    ///
+    /// the caller must guarentee that `h` and `a` have the same type as `H` and `At` on the trait implementation
+    unsafe fn call<'a>(
+        &'a self,
+        h: &'a (dyn Any + Send + Sync),
+        a: DynVar,
+        i: BusInterface,
+    ) -> BoxFuture<'a, HandlerOutput>;
+}
+
+impl<H, At, Rt, P, M> SharedHandlerCallableErased for SharedHandlerFn<H, At, Rt, P, M>
+where
+    P: for<'a> SharedAsyncFnPtr<'a, H, At, Rt, M> + Send + Sync + Copy + 'static,
+    H: DynDebug + Send + Sync + 'static,
+    At: DynDebug + Send + Sync + 'static,
+    Rt: DynDebug + Send + Sync + 'static,
+{
+    /// # Safety
+    ///
+    /// the caller must guarentee that `h` and `a` have the same type as `H` and `At` on the trait implementation
+    unsafe fn call<'a>(
+        &'a self,
+        h: &'a (dyn Any + Send + Sync),
+        a: DynVar,
+        i: BusInterface,
+    ) -> BoxFuture<'a, HandlerOutput> {
+        Box::pin(async move {
+            let h = dyn_var::downcast_ref_unchecked::<H>(h);
+            let a = a.try_to_unchecked::<At>();
+            match self.f.call(h, a, i) {
+                Ok(fut) => HandlerOutput::Return(DynVar::new(fut.await)),
+                Err(err) => HandlerOutput::BusError(FireEventError::from(err)),
+            }
         })
     }
 }
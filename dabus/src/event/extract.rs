@@ -0,0 +1,165 @@
+//! values that handlers can ask for, in addition to the event's arguments
+//!
+//! after `self` (which can be `&mut self` or `&self`) and the event's arguments (which can be left out),
+//! a handler can take up to four parameters implementing [`FromInterface`], in any order
+//!
+//! ```rust
+//! use dabus::{
+//!     event,
+//!     event::extract::{Deadline, Resource},
+//!     BusStop, DABus, EventRegister,
+//! };
+//!
+//! event!(GREET, String, String);
+//! event!(GREETING_COUNT, (), usize);
+//!
+//! struct Config {
+//!     greeting: &'static str,
+//! }
+//!
+//! #[derive(Debug, Default)]
+//! struct Greeter {
+//!     count: usize,
+//! }
+//!
+//! impl Greeter {
+//!     async fn greet(&mut self, name: String, config: Resource<Config>, deadline: Deadline) -> String {
+//!         assert!(deadline.0.is_some());
+//!         self.count += 1;
+//!         format!("{}, {name}!", config.greeting)
+//!     }
+//!
+//!     async fn count(&self) -> usize {
+//!         self.count
+//!     }
+//! }
+//!
+//! impl BusStop for Greeter {
+//!     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+//!         h.handler(GREET, Self::greet)
+//!             .handler(GREETING_COUNT, Self::count)
+//!     }
+//! }
+//!
+//! # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+//! let mut bus = DABus::new();
+//! bus.set_resource(Config { greeting: "Hello" });
+//! bus.set_timeout(Some(std::time::Duration::from_secs(1)));
+//! bus.register(Greeter::default());
+//!
+//! let greeting = bus.fire(GREET, String::from("World")).await.unwrap().ret();
+//! assert_eq!(greeting, "Hello, World!");
+//! assert_eq!(bus.fire(GREETING_COUNT, ()).await.unwrap().ret(), 1);
+//! # });
+//! ```
+//!
+//! if an extractor fails (for example a [`Resource`] that was never set), the handler is not run
+//! and the call fails with the extractor's error
+
+use std::{
+    any::type_name,
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    bus::{
+        error::{BaseFireEventError, CallEvent},
+        export::{SpanContext, TraceId},
+    },
+    BusInterface,
+};
+
+/// A value that can be passed to a handler, taken from the [`BusInterface`] of the call
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can not be extracted from the bus interface",
+    label = "handler parameters after the event's arguments must implement `FromInterface`"
+)]
+pub trait FromInterface: Sized {
+    /// # Errors
+    ///
+    /// if the value is not available for this call, in which case the handler is not run
+    fn from_interface(interface: &BusInterface) -> Result<Self, BaseFireEventError>;
+}
+
+impl FromInterface for BusInterface {
+    fn from_interface(interface: &BusInterface) -> Result<Self, BaseFireEventError> {
+        Ok(interface.clone())
+    }
+}
+
+/// the trace of the call, as it was when the handler started (see [`BusInterface::call_event`])
+impl FromInterface for CallEvent {
+    fn from_interface(interface: &BusInterface) -> Result<Self, BaseFireEventError> {
+        Ok(interface.call_event().clone())
+    }
+}
+
+impl FromInterface for SpanContext {
+    fn from_interface(interface: &BusInterface) -> Result<Self, BaseFireEventError> {
+        Ok(interface.span_context())
+    }
+}
+
+/// the id shared by every call made as part of the same top level call, for correlating them
+impl FromInterface for TraceId {
+    fn from_interface(interface: &BusInterface) -> Result<Self, BaseFireEventError> {
+        Ok(interface.span_context().trace_id)
+    }
+}
+
+/// The deadline of the call, if it has one (see [`BusInterface::deadline`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline(pub Option<SystemTime>);
+
+impl Deadline {
+    /// how long is left until the deadline, `None` if there is no deadline, or zero if it has passed
+    #[must_use]
+    pub fn remaining(&self) -> Option<Duration> {
+        self.0.map(|deadline| {
+            deadline
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO)
+        })
+    }
+}
+
+impl FromInterface for Deadline {
+    fn from_interface(interface: &BusInterface) -> Result<Self, BaseFireEventError> {
+        Ok(Self(interface.deadline()))
+    }
+}
+
+/// A value shared by the whole bus, set with [`DABus::set_resource`]
+///
+/// extracting a resource that was never set fails with [`BaseFireEventError::MissingResource`]
+///
+/// [`DABus::set_resource`]: crate::DABus::set_resource
+#[derive(Debug)]
+pub struct Resource<T>(pub Arc<T>);
+
+impl<T> Clone for Resource<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Resource<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Send + Sync + 'static> FromInterface for Resource<T> {
+    fn from_interface(interface: &BusInterface) -> Result<Self, BaseFireEventError> {
+        interface
+            .resource::<T>()
+            .map(Self)
+            .ok_or(BaseFireEventError::MissingResource {
+                resource: type_name::<T>(),
+            })
+    }
+}
@@ -1,14 +1,32 @@
 //! event declaration related things
 
 pub mod async_fn_ptr;
+pub mod extract;
+pub mod protocol;
 
 use std::{
     any::{type_name, TypeId},
+    collections::BTreeMap,
     marker::PhantomData,
+    sync::{PoisonError, RwLock},
 };
 
-use crate::unique_type;
-use async_fn_ptr::{AsyncFnPtr, HandlerCallableErased, HandlerFn};
+use std::error::Error;
+
+use crate::{
+    bus::{
+        error::CallTrace,
+        policy::{CircuitBreaker, Policy, RetryPolicy},
+    },
+    core::dyn_var::DynVar,
+    unique_type,
+    util::dyn_debug::DynDebug,
+    BusStop,
+};
+use async_fn_ptr::{
+    AsyncFnPtr, BlockingHandlerFn, FallibleHandlerFn, ForwardingHandlerFn, HandlerCallableErased,
+    HandlerFn, SharedAsyncFnPtr, SharedHandlerCallableErased, SharedHandlerFn,
+};
 
 /// type for declaring events.
 ///
@@ -24,7 +42,9 @@ pub struct EventDef<
     At,
     Rt = (), /* if At is `()`, than this event is eligeble for lazy evaluation */
 > {
-    pub(crate) name: &'static str,
+    name: &'static str,
+    /// builds the name of a generic event, see [`Event::name`]
+    name_fn: Option<fn() -> &'static str>,
     _tag: PhantomData<*const Tag /* dropck */>,
     _at: PhantomData<*const At /* also dropck */>,
     _rt: PhantomData<*const Rt /* also dropck */>,
@@ -37,7 +57,7 @@ unsafe impl<Tag: unique_type::Unique, At, Rt> Send for EventDef<Tag, At, Rt> {}
 impl<Tag: unique_type::Unique, At, Rt> EventDef<Tag, At, Rt> {
     /// Creates a new event defintion
     ///
-    /// for a easier (and safe) way of creating an event, see [`event!`]
+    /// for a easier (and safe) way of creating an event, see [`event!`] or [`Event`]
     ///
     /// # Safety
     ///
@@ -49,11 +69,134 @@ impl<Tag: unique_type::Unique, At, Rt> EventDef<Tag, At, Rt> {
     pub const unsafe fn new(name: &'static str) -> Self {
         Self {
             name,
+            name_fn: None,
+            _tag: PhantomData,
+            _at: PhantomData,
+            _rt: PhantomData,
+        }
+    }
+
+    /// the definition of an [`Event`], named by [`Event::name`]
+    const fn of_event(name_fn: fn() -> &'static str) -> Self
+    where
+        Tag: Event,
+    {
+        Self {
+            name: Tag::NAME,
+            name_fn: Some(name_fn),
             _tag: PhantomData,
             _at: PhantomData,
             _rt: PhantomData,
         }
     }
+
+    /// the name of the event, used in call traces and metrics
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name_fn.map_or(self.name, |name_fn| name_fn())
+    }
+
+    /// the id of the event's tag type, which identifies the event at runtime
+    pub(crate) fn tag_id(&self) -> TypeId
+    where
+        Tag: 'static,
+    {
+        TypeId::of::<Tag>()
+    }
+}
+
+/// Marks a stop as handling the event identified by `Tag`, see [`events!`]
+///
+/// `Tag` is the type of the same name declared by [`event!`] (or the type itself for an [`Event`]).
+/// this is only checked at compile time, registering handlers is still up to [`BusStop::registered_handlers`]
+///
+/// [`events!`]: crate::events!
+/// [`event!`]: crate::event!
+/// [`BusStop::registered_handlers`]: crate::BusStop::registered_handlers
+pub trait Handles<Tag: unique_type::Unique>: BusStop {}
+
+/// An event identified by its own type, as a safe alternative to [`event!`].
+///
+/// the event's definition is [`Event::DEF`], which can be used anywhere an [`EventDef`] is expected.
+/// it is usually implemented with `#[derive(Event)]` (with the `macros` feature), where the arguments default to the
+/// type itself
+///
+/// ```rust
+/// use dabus::{BusInterface, BusStop, DABus, Event, EventRegister};
+///
+/// /// prints a line
+/// #[derive(Debug)]
+/// pub struct Print(pub String);
+///
+/// impl Event for Print {
+///     type Args = Self;
+///     type Ret = ();
+///     const NAME: &'static str = "Print";
+/// }
+///
+/// #[derive(Debug)]
+/// struct Printer;
+///
+/// impl Printer {
+///     async fn print(&mut self, Print(line): Print, _i: BusInterface) {
+///         println!("{line}");
+///     }
+/// }
+///
+/// impl BusStop for Printer {
+///     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+///         h.handler(Print::DEF, Self::print)
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mut bus = DABus::new();
+/// bus.register(Printer);
+/// bus.fire(Print::DEF, Print(String::from("Hello, World!"))).await.unwrap();
+/// # }
+/// ```
+///
+/// [`event!`]: crate::event!
+pub trait Event: Sized + 'static {
+    type Args;
+    type Ret;
+    /// the name of the event, used in call traces
+    const NAME: &'static str;
+    /// the definition of this event
+    // Self is the only tag type of this def, since it is a type of its own
+    const DEF: &'static EventDef<Self, Self::Args, Self::Ret> = &EventDef::of_event(Self::name);
+
+    /// the name of the event in call traces and metrics, which is [`Event::NAME`] unless overridden.
+    /// generic events declared with [`event!`](crate::event!) include their type parameters, such as `STORE<u32>`
+    #[must_use]
+    fn name() -> &'static str {
+        Self::NAME
+    }
+}
+
+/// the name of an instantiation of a generic event, such as `STORE<u32>`, which is only built once for each `E`
+///
+/// statics can not be generic, so the names are cached by type. once built, a name is looked up under a read lock,
+/// and the runtime only asks for it once per call (when the call's [`CallEvent`] is created)
+///
+/// [`CallEvent`]: crate::bus::error::CallEvent
+#[doc(hidden)]
+pub fn generic_name<E: 'static>(name: &str, params: &[&str]) -> &'static str {
+    static NAMES: RwLock<BTreeMap<TypeId, &'static str>> = RwLock::new(BTreeMap::new());
+    let id = TypeId::of::<E>();
+    if let Some(name) = NAMES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&id)
+    {
+        return name;
+    }
+    NAMES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(id)
+        .or_insert_with(|| Box::leak(format!("{name}<{}>", params.join(", ")).into_boxed_str()))
 }
 
 /// abstraction for registering handlers
@@ -64,39 +207,287 @@ pub struct EventRegister<S: ?Sized> {
         Box<dyn HandlerCallableErased + Send + Sync + 'static>,
         String,
     )>,
+    /// handlers registered with [`EventRegister::shared_handler`]
+    pub(crate) shared_handlers: Vec<(
+        TypeId,
+        Box<dyn SharedHandlerCallableErased + Send + Sync + 'static>,
+        String,
+    )>,
+    pub(crate) required: Vec<(TypeId, &'static str)>,
+    pub(crate) policies: Vec<(TypeId, Policy)>,
     _stop_t: PhantomData<S>,
 }
 
-impl<S: Sync + Send + 'static> EventRegister<S> {
+impl<S: DynDebug + Sync + Send + 'static> EventRegister<S> {
     pub(crate) const fn new() -> Self {
         Self {
             handlers: vec![],
+            shared_handlers: vec![],
+            required: vec![],
+            policies: vec![],
             _stop_t: PhantomData,
         }
     }
 
     // do not the generic async function pointers
     #[must_use]
-    pub fn handler<Tag, At, Rt, P>(mut self, def: &'static EventDef<Tag, At, Rt>, func: P) -> Self
+    pub fn handler<Tag, At, Rt, P, M>(self, def: &'static EventDef<Tag, At, Rt>, func: P) -> Self
     where
         Tag: unique_type::Unique + Send + Sync + 'static,
-        At: Send + Sync + 'static,
-        Rt: Send + Sync + 'static,
-        P: for<'a> AsyncFnPtr<'a, S, At, Rt> + Copy + Send + Sync + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        Rt: DynDebug + Send + Sync + 'static,
+        P: for<'a> AsyncFnPtr<'a, S, At, Rt, M> + Copy + Send + Sync + 'static,
+        M: 'static,
     {
-        self.handlers.push((
+        self.push(def, Box::new(HandlerFn::new(func)))
+    }
+
+    /// registers a handler returning `Result<T, E>`, like [`EventRegister::handler`].
+    ///
+    /// when the handler returns `Err`, the call is recorded in the [`CallTrace`] as [`Resolution::HandlerError`]
+    /// (and found by [`CallTrace::source`]). the `Err` is still returned to the caller as usual
+    ///
+    /// [`CallTrace`]: crate::bus::error::CallTrace
+    /// [`CallTrace::source`]: crate::bus::error::CallTrace::source
+    /// [`Resolution::HandlerError`]: crate::bus::error::Resolution::HandlerError
+    #[must_use]
+    pub fn fallible_handler<Tag, At, T, E, P, M>(
+        self,
+        def: &'static EventDef<Tag, At, Result<T, E>>,
+        func: P,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + Send + Sync + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        T: Send + Sync + 'static,
+        E: Error + Send + Sync + 'static,
+        Result<T, E>: DynDebug,
+        P: for<'a> AsyncFnPtr<'a, S, At, Result<T, E>, M> + Copy + Send + Sync + 'static,
+        M: 'static,
+    {
+        self.push(def, Box::new(FallibleHandlerFn::new(func)))
+    }
+
+    /// registers a handler returning `Result<Rt, CallTrace>` for an event returning `Rt`, like [`EventRegister::handler`].
+    ///
+    /// this allows for forwarding errors from nested calls with `?`:
+    ///
+    /// ```rust
+    /// # use dabus::{event, BusInterface, BusStop, EventRegister, bus::error::CallTrace};
+    /// event!(OUTER, (), u32);
+    /// event!(INNER, (), u32);
+    ///
+    /// #[derive(Debug)]
+    /// struct Outer;
+    ///
+    /// impl Outer {
+    ///     async fn outer(&mut self, _: (), mut i: BusInterface) -> Result<u32, CallTrace> {
+    ///         Ok(i.fire(INNER, ()).await? + 1)
+    ///     }
+    /// }
+    ///
+    /// impl BusStop for Outer {
+    ///     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+    ///         h.forwarding_handler(OUTER, Self::outer)
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// when the handler returns `Err`, the call is resolved with [`Resolution::NestedCallError`] and the trace of the
+    /// failed call is passed on to the caller (the same way [`BusInterface::fwd_bus_err`] does)
+    ///
+    /// [`Resolution::NestedCallError`]: crate::bus::error::Resolution::NestedCallError
+    /// [`BusInterface::fwd_bus_err`]: crate::BusInterface::fwd_bus_err
+    #[must_use]
+    pub fn forwarding_handler<Tag, At, Rt, P, M>(
+        self,
+        def: &'static EventDef<Tag, At, Rt>,
+        func: P,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + Send + Sync + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        Rt: DynDebug + Send + Sync + 'static,
+        P: for<'a> AsyncFnPtr<'a, S, At, Result<Rt, CallTrace>, M> + Copy + Send + Sync + 'static,
+        M: 'static,
+    {
+        self.push(def, Box::new(ForwardingHandlerFn::new(func)))
+    }
+
+    /// adds a synchronous handler, which is run through the bus's [`Spawner`] so it can block without stalling the
+    /// executor (see [`blocking`])
+    ///
+    /// [`Spawner`]: crate::bus::blocking::Spawner
+    /// [`blocking`]: crate::bus::blocking
+    #[must_use]
+    pub fn blocking_handler<Tag, At, Rt, F>(
+        self,
+        def: &'static EventDef<Tag, At, Rt>,
+        func: F,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + Send + Sync + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        Rt: DynDebug + Send + Sync + 'static,
+        F: Fn(&mut S, At) -> Rt + Copy + Send + Sync + 'static,
+    {
+        self.push(def, Box::new(BlockingHandlerFn::new(func)))
+    }
+
+    /// registers a handler taking `&self`, which can run at the same time as the other shared handlers of this stop
+    ///
+    /// other handlers (even ones taking `&self`, if they were registered with [`EventRegister::handler`]) still have the
+    /// stop to themselves, and wait for any shared handlers to finish first. while one of them is waiting, new shared
+    /// calls wait behind it instead of joining the running ones. since shared handlers only need to borrow the stop,
+    /// they can also make nested calls to each other (even while another handler is waiting)
+    ///
+    /// ```rust
+    /// use std::collections::BTreeMap;
+    /// use dabus::{event, BusInterface, BusStop, DABus, EventRegister};
+    ///
+    /// event!(GET, &'static str, Option<String>);
+    /// event!(GET_ALL, Vec<&'static str>, Vec<Option<String>>);
+    /// event!(SET, (&'static str, String), ());
+    ///
+    /// #[derive(Debug, Default)]
+    /// struct Config {
+    ///     values: BTreeMap<&'static str, String>,
+    /// }
+    ///
+    /// impl Config {
+    ///     async fn get(&self, key: &'static str, _i: BusInterface) -> Option<String> {
+    ///         self.values.get(key).cloned()
+    ///     }
+    ///
+    ///     async fn get_all(&self, keys: Vec<&'static str>, mut i: BusInterface) -> Vec<Option<String>> {
+    ///         let mut values = vec![];
+    ///         for key in keys {
+    ///             // a nested call to this stop, while it is still being used by this handler
+    ///             values.push(i.fire(GET, key).await.unwrap());
+    ///         }
+    ///         values
+    ///     }
+    ///
+    ///     async fn set(&mut self, (key, value): (&'static str, String), _i: BusInterface) {
+    ///         self.values.insert(key, value);
+    ///     }
+    /// }
+    ///
+    /// impl BusStop for Config {
+    ///     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+    ///         h.shared_handler(GET, Self::get)
+    ///             .shared_handler(GET_ALL, Self::get_all)
+    ///             .handler(SET, Self::set)
+    ///     }
+    /// }
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let mut bus = DABus::new();
+    /// bus.register(Config::default());
+    /// bus.fire(SET, ("name", String::from("dabus"))).await.unwrap();
+    /// let values = bus.fire(GET_ALL, vec!["name", "version"]).await.unwrap().ret();
+    /// assert_eq!(values, [Some(String::from("dabus")), None]);
+    /// # });
+    /// ```
+    #[must_use]
+    pub fn shared_handler<Tag, At, Rt, P, M>(
+        mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        func: P,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + Send + Sync + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        Rt: DynDebug + Send + Sync + 'static,
+        P: for<'a> SharedAsyncFnPtr<'a, S, At, Rt, M> + Copy + Send + Sync + 'static,
+        M: 'static,
+    {
+        self.shared_handlers.push((
             TypeId::of::<Tag>(),
-            Box::new(HandlerFn::new(func)),
-            format!(
-                "handler: {}, name: {}, args: {}, return: {}, type_id: {:?}",
-                type_name::<S>(),
-                def.name,
-                type_name::<At>(),
-                type_name::<Rt>(),
-                TypeId::of::<Tag>(),
-            ),
+            Box::new(SharedHandlerFn::new(func)),
+            Self::describe(def),
         ));
-        let _ = def;
         self
     }
+
+    /// declares that this stop fires `def`, so that [`DABus::verify`] can check that it is handled
+    ///
+    /// [`DABus::verify`]: crate::DABus::verify
+    #[must_use]
+    pub fn requires<Tag, At, Rt>(mut self, def: &'static EventDef<Tag, At, Rt>) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+    {
+        self.required.push((TypeId::of::<Tag>(), def.name()));
+        self
+    }
+
+    /// declares that this stop fires every event of the protocol `P`, like [`EventRegister::requires`]
+    #[must_use]
+    pub fn requires_protocol<P: protocol::Protocol>(mut self) -> Self {
+        self.required.extend(P::events());
+        self
+    }
+
+    /// retries calls of `def` handled by this stop when they fail, see [`policy`]
+    ///
+    /// [`policy`]: crate::bus::policy
+    #[must_use]
+    pub fn retry<Tag, At, Rt>(
+        mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        policy: RetryPolicy,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+        At: DynDebug + Clone + Send + Sync + 'static,
+    {
+        self.policies
+            .push((def.tag_id(), Policy::Retry(policy, DynVar::clone_as::<At>)));
+        self
+    }
+
+    /// fails calls of `def` handled by this stop without running them after too many failures, see [`policy`]
+    ///
+    /// [`policy`]: crate::bus::policy
+    #[must_use]
+    pub fn circuit_breaker<Tag, At, Rt>(
+        mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        breaker: CircuitBreaker,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+    {
+        self.policies.push((def.tag_id(), Policy::Breaker(breaker)));
+        self
+    }
+
+    fn push<Tag, At, Rt>(
+        mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        handler: Box<dyn HandlerCallableErased + Send + Sync + 'static>,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+    {
+        self.handlers
+            .push((TypeId::of::<Tag>(), handler, Self::describe(def)));
+        self
+    }
+
+    /// describes a handler for debug logs
+    fn describe<Tag, At, Rt>(def: &'static EventDef<Tag, At, Rt>) -> String
+    where
+        Tag: unique_type::Unique + 'static,
+    {
+        format!(
+            "handler: {}, name: {}, args: {}, return: {}, type_id: {:?}",
+            type_name::<S>(),
+            def.name(),
+            type_name::<At>(),
+            type_name::<Rt>(),
+            TypeId::of::<Tag>(),
+        )
+    }
 }
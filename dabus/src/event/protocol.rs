@@ -0,0 +1,93 @@
+//! typed clients for a set of events, see [`protocol!`]
+//!
+//! [`protocol!`]: crate::protocol!
+
+use std::{any::TypeId, future::Future};
+
+use crate::{
+    bus::error::CallTrace, event::EventDef, unique_type, util::dyn_debug::DynDebug, BusInterface,
+    DABus, FireEvent,
+};
+
+/// Something that events can be fired through, used by the clients declared with [`protocol!`]
+///
+/// implemented for [`DABus`] (for top level calls) and [`BusInterface`] (for calls from handlers),
+/// as well as mutable references to either of them
+///
+/// [`protocol!`]: crate::protocol!
+pub trait Caller {
+    /// fires `def`, returning only the value returned by the handler
+    ///
+    /// # Errors
+    ///
+    /// if the call fails, see [`BusInterface::fire`]
+    fn call<Tag, At, Rt>(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        args: At,
+    ) -> impl Future<Output = Result<Rt, CallTrace>>
+    where
+        Tag: unique_type::Unique,
+        At: DynDebug + Sync + Send + 'static,
+        Rt: DynDebug + Sync + Send + 'static;
+}
+
+impl Caller for BusInterface {
+    fn call<Tag, At, Rt>(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        args: At,
+    ) -> impl Future<Output = Result<Rt, CallTrace>>
+    where
+        Tag: unique_type::Unique,
+        At: DynDebug + Sync + Send + 'static,
+        Rt: DynDebug + Sync + Send + 'static,
+    {
+        self.fire(def, args)
+    }
+}
+
+impl Caller for DABus {
+    async fn call<Tag, At, Rt>(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        args: At,
+    ) -> Result<Rt, CallTrace>
+    where
+        Tag: unique_type::Unique,
+        At: DynDebug + Sync + Send + 'static,
+        Rt: DynDebug + Sync + Send + 'static,
+    {
+        self.fire(def, args).await.map(FireEvent::ret)
+    }
+}
+
+impl<C: Caller> Caller for &mut C {
+    fn call<Tag, At, Rt>(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        args: At,
+    ) -> impl Future<Output = Result<Rt, CallTrace>>
+    where
+        Tag: unique_type::Unique,
+        At: DynDebug + Sync + Send + 'static,
+        Rt: DynDebug + Sync + Send + 'static,
+    {
+        (**self).call(def, args)
+    }
+}
+
+/// A named set of events, implemented by the clients declared with [`protocol!`]
+///
+/// used to check that every event of the protocol is handled, with [`EventRegister::requires_protocol`]
+/// or [`DABus::verify_protocol`]
+///
+/// [`protocol!`]: crate::protocol!
+/// [`EventRegister::requires_protocol`]: crate::EventRegister::requires_protocol
+pub trait Protocol {
+    /// the name of the protocol, used when reporting missing handlers
+    const NAME: &'static str;
+
+    /// the tag ids and names of every event in the protocol
+    fn events() -> Vec<(TypeId, &'static str)>;
+}
@@ -1,21 +1,56 @@
-use std::any::TypeId;
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::BTreeMap,
+    fmt::{self, Debug, Formatter},
+    sync::{Arc, Weak},
+    time::SystemTime,
+};
 
 use flume::Sender;
 
 use crate::{
-    bus::error::{CallEvent, CallTrace},
+    bus::{
+        blocking::{BlockingTask, Spawner, ThreadSpawner},
+        error::{BaseFireEventError, CallEvent, CallTrace},
+        export::SpanContext,
+    },
     core::dyn_var::DynVar,
     unique_type,
     util::dyn_debug::DynDebug,
     EventDef,
 };
 
+/// where the runtime sends the result of a nested call, `Ok(None)` if it was optional and not handled
+pub(crate) type Responder = Sender<Result<Option<DynVar>, CallTrace>>;
+
+/// values shared by the whole bus, see [`DABus::set_resource`](crate::DABus::set_resource)
+pub(crate) type Resources = BTreeMap<TypeId, Arc<dyn Any + Send + Sync>>;
+
+/// what a handler can find out about the call it is handling
+pub(crate) struct CallInfo {
+    pub event: CallEvent,
+    pub resources: Option<Arc<Resources>>,
+    /// `None` for [`ThreadSpawner`]
+    pub spawner: Option<Arc<dyn Spawner + Send + Sync>>,
+}
+
+impl Debug for CallInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallInfo")
+            .field("event", &self.event)
+            .field("resources", &self.resources)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 pub enum BusInterfaceEvent {
     Fire {
         def: TypeId,
         args: DynVar,
-        responder: Sender<Result<DynVar, CallTrace>>,
+        /// made with [`BusInterface::try_fire`]
+        optional: bool,
+        responder: Responder,
         trace_data: CallEvent,
     },
     FwdBusError {
@@ -28,16 +63,119 @@ pub enum BusInterfaceEvent {
 ///
 /// This is passed to handlers, giving them a way of running actions on the bus that they are being run from.
 ///
+/// It can be cloned to make several nested calls at once, for example
+///
+/// ```rust
+/// # use dabus::{event, BusInterface, bus::error::CallTrace};
+/// event!(GET_NAME, u32, String);
+/// event!(GET_AGE, u32, u8);
+///
+/// async fn describe(id: u32, mut i: BusInterface) -> Result<String, CallTrace> {
+///     let mut i2 = i.clone();
+///     let (name, age) = futures::join!(i.fire(GET_NAME, id), i2.fire(GET_AGE, id));
+///     Ok(format!("{} ({})", name?, age?))
+/// }
+/// ```
+///
 /// [`DABus`]: crate::bus::DABus
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(clippy::module_name_repetitions)]
 pub struct BusInterface {
     pub(crate) channel: Sender<BusInterfaceEvent>,
+    context: SpanContext,
+    /// dropped by the runtime when the handler returns
+    liveness: Weak<()>,
+    info: Arc<CallInfo>,
+    /// set with [`BusInterface::with_deadline`]
+    deadline: Option<SystemTime>,
 }
 
 impl BusInterface {
-    pub(crate) const fn new(sender: Sender<BusInterfaceEvent>) -> Self {
-        Self { channel: sender }
+    pub(crate) fn new(
+        sender: Sender<BusInterfaceEvent>,
+        context: SpanContext,
+        liveness: Weak<()>,
+        info: CallInfo,
+    ) -> Self {
+        Self {
+            channel: sender,
+            context,
+            liveness,
+            info: Arc::new(info),
+            deadline: None,
+        }
+    }
+
+    /// The trace of the call this interface was given to, as it was when the handler started
+    #[must_use]
+    pub fn call_event(&self) -> &CallEvent {
+        &self.info.event
+    }
+
+    /// The deadline for nested calls made through this interface, which is the earlier of the deadline of the call
+    /// it was given to and any set with [`BusInterface::with_deadline`]
+    ///
+    /// nested calls that have not been started by their deadline fail with [`BaseFireEventError::DeadlineExceeded`].
+    /// handlers that are already running are not stopped, but can use it to give up early
+    #[must_use]
+    pub fn deadline(&self) -> Option<SystemTime> {
+        match (self.info.event.deadline, self.deadline) {
+            (Some(call), Some(own)) => Some(call.min(own)),
+            (call, own) => call.or(own),
+        }
+    }
+
+    /// Sets a deadline for nested calls made through this interface (and the calls they make), see
+    /// [`BusInterface::deadline`]. a later deadline than the one of the current call has no effect
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// runs a blocking handler through the bus's [`Spawner`]
+    pub(crate) fn spawn_blocking(&self, task: BlockingTask) {
+        match &self.info.spawner {
+            Some(spawner) => spawner.spawn_blocking(task),
+            None => ThreadSpawner.spawn_blocking(task),
+        }
+    }
+
+    /// A value shared by the whole bus, see [`DABus::set_resource`](crate::DABus::set_resource)
+    #[must_use]
+    pub fn resource<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.info
+            .resources
+            .as_ref()?
+            .get(&TypeId::of::<T>())?
+            .clone()
+            .downcast()
+            .ok()
+    }
+
+    /// Checks if the handler this interface was given to has returned.
+    ///
+    /// once it has, all events fired through this interface will fail with [`BaseFireEventError::InterfaceExpired`]
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.liveness.strong_count() == 0
+    }
+
+    /// the error for when the runtime is no longer listening to this interface
+    fn closed_error(&self) -> BaseFireEventError {
+        if self.is_expired() {
+            BaseFireEventError::InterfaceExpired
+        } else {
+            BaseFireEventError::InterfaceClosed
+        }
+    }
+
+    /// The trace and span ids of the call this interface was given to, for correlating with other telemetry.
+    ///
+    /// nested calls made through this interface will be part of the same trace
+    #[must_use]
+    pub const fn span_context(&self) -> SpanContext {
+        self.context
     }
 
     /// Fires an event on the bus, running appropreate handlers and returning the result.
@@ -52,15 +190,19 @@ impl BusInterface {
     ///
     /// # Panics
     ///
-    /// if a handler that is called panics (or the runtime is broken)
+    /// if a handler that is called panics
     ///
     /// # Errors
     ///
-    /// if there is some (expected) error with the runtime. currently this only includes not finding an appropreate handler
+    /// if the call fails, see [`BaseFireEventError`] for the errors reported by the runtime itself
     ///
     /// # Notes
     /// like all functions on this struct, this does not execute an event iself but rather forwards it to the current runtime.
-    /// this means that if useing this after the scope of the handler it was given to has ended should be considered **Undefined Behavior** (eventually there will be some safeguard to fix this)
+    /// this means that it only works while the handler it was given to is running. the interface may be cloned, or moved into
+    /// a spawned task, but once the handler returns this will fail with [`BaseFireEventError::InterfaceExpired`]
+    ///
+    /// nested calls made at the same time run concurrently, except for calls to the same stop, which wait for it to be free.
+    /// calls to a stop that can not become free (because it is waiting on the caller) fail with [`BaseFireEventError::HandlerBusy`]
     ///
     /// [`DABus::fire`]: crate::bus::DABus::fire
     pub async fn fire<
@@ -73,46 +215,153 @@ impl BusInterface {
         args: At,
     ) -> Result<Rt, CallTrace> {
         let trace_data = CallEvent::from_event_def(def, &args);
+        let fallback_trace = trace_data.clone();
+        self.fire_inner(def, args, false).await?.ok_or_else(|| {
+            CallTrace::bus_error(
+                fallback_trace,
+                BaseFireEventError::RuntimeInvariant("a required call was treated as optional"),
+            )
+        })
+    }
+
+    /// Fires an event if a stop handles it, like [`BusInterface::fire`]
+    ///
+    /// # Returns
+    ///
+    /// `Ok(None)` if no stop handles the event (see [`DABus::has_handler`]), it has no default value, and there is
+    /// no dead letter handler (see [`fallback`]), without counting it as an error. otherwise the event is fired as
+    /// usual (see [`DABus::try_fire`])
+    ///
+    /// # Errors
+    ///
+    /// see [`BusInterface::fire`]
+    ///
+    /// [`DABus::has_handler`]: crate::bus::DABus::has_handler
+    /// [`DABus::try_fire`]: crate::bus::DABus::try_fire
+    /// [`fallback`]: crate::bus::fallback
+    pub async fn try_fire<
+        Tag: unique_type::Unique,
+        At: DynDebug + Sync + Send + 'static,
+        Rt: DynDebug + Sync + Send + 'static,
+    >(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        args: At,
+    ) -> Result<Option<Rt>, CallTrace> {
+        self.fire_inner(def, args, true).await
+    }
+
+    async fn fire_inner<
+        Tag: unique_type::Unique,
+        At: DynDebug + Sync + Send + 'static,
+        Rt: DynDebug + Sync + Send + 'static,
+    >(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        args: At,
+        optional: bool,
+    ) -> Result<Option<Rt>, CallTrace> {
+        let mut trace_data = CallEvent::from_event_def(def, &args);
+        trace_data.deadline = self.deadline();
         let _ = def;
         let def = TypeId::of::<Tag>();
         let args = DynVar::new(args);
-        let (responder, response) = flume::bounded::<Result<DynVar, CallTrace>>(1);
-        self.channel
-            .send(BusInterfaceEvent::Fire {
+        let (responder, response) = flume::bounded(1);
+        if self.is_expired() {
+            return Err(CallTrace::bus_error(
+                trace_data,
+                BaseFireEventError::InterfaceExpired,
+            ));
+        }
+        let fallback_trace = trace_data.clone();
+        if self
+            .channel
+            .send_async(BusInterfaceEvent::Fire {
                 def,
                 args,
+                optional,
                 responder,
                 trace_data,
             })
-            .unwrap();
-        Ok(response
-            .into_recv_async()
             .await
-            .unwrap()?
-            .try_to::<Rt>()
-            .unwrap())
+            .is_err()
+        {
+            return Err(CallTrace::bus_error(fallback_trace, self.closed_error()));
+        }
+        let Ok(response) = response.into_recv_async().await else {
+            return Err(CallTrace::bus_error(fallback_trace, self.closed_error()));
+        };
+        let Some(value) = response? else {
+            return Ok(None);
+        };
+        value.try_to::<Rt>().map(Some).map_err(|value| {
+            CallTrace::bus_error(
+                fallback_trace,
+                BaseFireEventError::TypeMismatch {
+                    expected: type_name::<Rt>(),
+                    found: value.type_name(),
+                },
+            )
+        })
+    }
+
+    /// Fires the same event once for each of `args`, running the calls at the same time.
+    ///
+    /// the results are in the same order as `args`. since all of the calls go to the same stop, they will run one after the other,
+    /// see [`BusInterface`] for making concurrent calls to different events
+    pub async fn fire_all<
+        Tag: unique_type::Unique,
+        At: DynDebug + Sync + Send + 'static,
+        Rt: DynDebug + Sync + Send + 'static,
+    >(
+        &mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        args: impl IntoIterator<Item = At>,
+    ) -> Vec<Result<Rt, CallTrace>> {
+        let calls = args.into_iter().map(|args| {
+            let mut interface = self.clone();
+            async move { interface.fire(def, args).await }
+        });
+        futures::future::join_all(calls).await
     }
 
     /// takes a error (from a nested call, presumablely) and forwards it to the caller of the current event (via the runtime and a deal with the devil)
     ///
     /// this is a easy way to handle errors, as it will forward the error, and can produce nice backtraces
     ///
-    /// # Panics
-    ///
-    /// it shouldent, unless something is horribly wrong with the library
-    ///
     /// # Footguns
     ///
     /// - this function (from the perspective of the handler) will never return, but from the persepective of the program it will, so keep that in mind.
     ///
     /// - see the `Notes` section in [`BusInterface::fire`]
+    ///
+    /// - the handler's future is dropped without completing, along with anything it was holding. this includes the stop
+    ///   it was called on, so all later calls to that stop will fail with [`BaseFireEventError::RuntimeInvariant`].
+    ///   handlers registered with [`EventRegister::forwarding_handler`] can instead return the error with `?`
+    ///
+    /// # Panics
+    ///
+    /// if the error can not be forwarded, because the interface has expired (see [`BusInterface::is_expired`]) or is
+    /// being used outside of the handler it was given to (for example from a spawned task). the panic message includes
+    /// the error that could not be forwarded
+    ///
+    /// [`EventRegister::forwarding_handler`]: crate::EventRegister::forwarding_handler
     pub async fn fwd_bus_err(&self, error: CallTrace) -> ! {
         let (blocker, blocks) = flume::bounded::<()>(1);
-        self.channel
-            .send(BusInterfaceEvent::FwdBusError { error, blocker })
-            .unwrap();
-        blocks.recv_async().await.unwrap();
-        unreachable!()
+        let summary = format!("{error:?}");
+        if self
+            .channel
+            .send_async(BusInterfaceEvent::FwdBusError { error, blocker })
+            .await
+            .is_ok()
+        {
+            // the runtime drops the handler before `blocker`, so this only returns if the handler is being polled elsewhere
+            let _ = blocks.recv_async().await;
+        }
+        panic!(
+            "could not forward an error, the bus interface is no longer connected to the runtime ({}): {summary}",
+            self.closed_error()
+        )
     }
 }
 
@@ -137,3 +386,40 @@ impl<T: Send> BusErrorUtil<T> for Result<T, CallTrace> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{BusInterface, CallInfo};
+    use crate::bus::{
+        error::{BaseFireEventError, CallEvent, Resolution},
+        export::SpanContext,
+    };
+
+    crate::event!(TEST_EVENT, (), ());
+
+    // the runtime drops the liveness token before the channel, so this can not happen through the public api
+    #[tokio::test]
+    async fn calls_through_a_disconnected_interface_are_interface_closed() {
+        let (channel, recv) = flume::unbounded();
+        drop(recv);
+        let liveness = Arc::new(());
+        let info = CallInfo {
+            event: CallEvent::from_event_def(TEST_EVENT, &()),
+            resources: None,
+            spawner: None,
+        };
+        let mut interface = BusInterface::new(
+            channel,
+            SpanContext::new_root(),
+            Arc::downgrade(&liveness),
+            info,
+        );
+        let trace = interface.fire(TEST_EVENT, ()).await.unwrap_err();
+        assert!(matches!(
+            trace.source().and_then(|event| event.resolution),
+            Some(Resolution::BusError(err)) if matches!(err.base(), BaseFireEventError::InterfaceClosed)
+        ));
+    }
+}
@@ -1,7 +1,6 @@
-#![feature(downcast_unchecked)]
-#![allow(incomplete_features)]
-#![feature(specialization)]
-#![feature(extract_if)]
+#![cfg_attr(feature = "nightly", feature(downcast_unchecked))]
+#![cfg_attr(feature = "nightly", allow(incomplete_features))]
+#![cfg_attr(feature = "nightly", feature(specialization))]
 
 #[allow(unused_imports)]
 #[macro_use]
@@ -19,13 +18,12 @@ pub(crate) mod stop;
 pub mod unique_type;
 pub(crate) mod util;
 
-#[doc(hidden)]
-pub use ::concat_idents as __concat_idents;
-
-pub use bus::{DABus, FireEvent};
-pub use event::{EventDef, EventRegister};
+pub use bus::{DABus, FireEvent, StopHandle};
+#[cfg(feature = "macros")]
+pub use dabus_macros::{bus_stop, handles, Event};
+pub use event::{Event, EventDef, EventRegister};
 pub use interface::{BusErrorUtil, BusInterface};
-pub use stop::BusStop;
+pub use stop::{fn_stop::FnStop, BusStop};
 
 /// things that are just implementation details of the crate,
 /// but might be nice to use (on a related topic to this crate)
@@ -34,5 +32,7 @@ pub use stop::BusStop;
 pub mod extras {
     pub use crate::core::dyn_var::DynVar;
     pub use crate::event::async_fn_ptr;
-    pub use crate::util::{async_util, dyn_debug::DynDebug, AsAny, PossiblyClone, TypeNamed};
+    #[cfg(feature = "nightly")]
+    pub use crate::util::PossiblyClone;
+    pub use crate::util::{async_util, dyn_debug::DynDebug, AsAny, TypeNamed};
 }
@@ -10,26 +10,302 @@
 ///
 /// # Args
 /// (
+///     attributes: doc comments and other attributes (such as `#[cfg]`) for the event
+///     visibility: the visibility of the event, like any other item. when it is left out the event is `pub`
+///         (as it always was before visibilities were accepted), so private events are declared with `pub(self)`
 ///     name: the name of the const variable produced
 ///     arg: the type of the arguments for the event
 ///     ret: the return type of the event
 /// )
 ///
+/// ```rust
+/// mod events {
+///     # use dabus::event;
+///     event!(
+///         /// prints a line
+///         pub(crate) PRINT_EVENT, String, ()
+///     );
+///     // `pub`
+///     event!(FLUSH_EVENT, (), ());
+///     event!(pub(self) INTERNAL_EVENT, (), ());
+/// }
+///
+/// let _ = (events::PRINT_EVENT, events::FLUSH_EVENT);
+/// ```
+///
+/// this also declares a hidden type with the same name as the event, which is used to identify it
+/// (see [`Handles`](crate::event::Handles))
+///
+/// # Generic events
+///
+/// events can also be generic over type parameters, in which case the event is declared as a type
+/// implementing [`Event`](crate::event::Event) instead of a const, and is referred to as `NAME::<T>::DEF`.
+/// every instantiation is a separate event (named after its type parameters in call traces and metrics, such as
+/// `STORE<u32>`), so a stop can handle only some of them, or be generic itself
+///
+/// ```rust
+/// # use dabus::{event, BusInterface, BusStop, DABus, Event, EventRegister};
+/// # use std::fmt::Debug;
+/// event!(
+///     /// stores a value
+///     pub STORE<T>, T, ()
+/// );
+/// event!(pub LOAD<T>, (), Option<T>);
+///
+/// #[derive(Debug)]
+/// struct Storage<T> {
+///     value: Option<T>,
+/// }
+///
+/// impl<T: Clone + Debug + Send + Sync + 'static> Storage<T> {
+///     async fn store(&mut self, value: T, _i: BusInterface) {
+///         self.value = Some(value);
+///     }
+///
+///     async fn load(&mut self, _: (), _i: BusInterface) -> Option<T> {
+///         self.value.clone()
+///     }
+/// }
+///
+/// impl<T: Clone + Debug + Send + Sync + 'static> BusStop for Storage<T> {
+///     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+///         h.handler(STORE::<T>::DEF, Self::store)
+///             .handler(LOAD::<T>::DEF, Self::load)
+///     }
+/// }
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let mut bus = DABus::new();
+/// bus.register(Storage::<u32> { value: None });
+/// bus.register(Storage::<String> { value: None });
+///
+/// bus.fire(STORE::<u32>::DEF, 5).await.unwrap();
+/// assert_eq!(bus.fire(LOAD::<u32>::DEF, ()).await.unwrap().ret(), Some(5));
+/// assert_eq!(bus.fire(LOAD::<String>::DEF, ()).await.unwrap().ret(), None);
+/// # });
+///
+/// assert_eq!(STORE::<u32>::DEF.name(), "STORE<u32>");
+/// ```
+///
+/// the type parameters are only required to be `'static`
 #[macro_export]
 macro_rules! event {
-    ($name:ident, $arg:ty, $ret:ty) => {
-        $crate::__concat_idents::concat_idents!(unique_t = __, $name, _, unique {
-            #[doc(hidden)]
-            #[allow(non_camel_case_types)]
-            pub struct unique_t;
-            unsafe impl $crate::unique_type::Unique for unique_t {}
-            pub const $name: &'static $crate::event::EventDef<
-                unique_t,
-                $arg,
-                $ret,
-            > = &unsafe {
-                $crate::event::EventDef::new(concat!(stringify!($name)))
-            };
-        });
+    ($(#[$attr:meta])* $name:ident $(<$($param:ident),+ $(,)?>)?, $arg:ty, $ret:ty) => {
+        $crate::event!(@declare $(#[$attr])* pub $name $(<$($param),+>)?, $arg, $ret);
+    };
+    ($(#[$attr:meta])* $vis:vis $name:ident $(<$($param:ident),+ $(,)?>)?, $arg:ty, $ret:ty) => {
+        $crate::event!(@declare $(#[$attr])* $vis $name $(<$($param),+>)?, $arg, $ret);
+    };
+    (@declare $(#[$attr:meta])* $vis:vis $name:ident<$($param:ident),+ $(,)?>, $arg:ty, $ret:ty) => {
+        $(#[$attr])*
+        #[allow(non_camel_case_types)]
+        $vis struct $name<$($param),+>(::core::marker::PhantomData<fn() -> ($($param,)+)>);
+        $(#[$attr])*
+        impl<$($param: 'static),+> $crate::event::Event for $name<$($param),+> {
+            type Args = $arg;
+            type Ret = $ret;
+            const NAME: &'static str = stringify!($name);
+
+            fn name() -> &'static str {
+                $crate::event::generic_name::<Self>(
+                    stringify!($name),
+                    &[$(::core::any::type_name::<$param>()),+],
+                )
+            }
+        }
+    };
+    (@declare $(#[$attr:meta])* $vis:vis $name:ident, $arg:ty, $ret:ty) => {
+        $(#[$attr])*
+        #[doc(hidden)]
+        #[allow(non_camel_case_types)]
+        $vis struct $name {}
+        $(#[$attr])*
+        unsafe impl $crate::unique_type::Unique for $name {}
+        $(#[$attr])*
+        $vis const $name: &'static $crate::event::EventDef<$name, $arg, $ret> =
+            &unsafe { $crate::event::EventDef::new(stringify!($name)) };
+    };
+}
+
+/// declares a group of events (like [`event!`]), along with a marker trait for stops that handle all of them
+///
+/// the marker trait requires [`Handles`](crate::event::Handles) for every event in the group, so implementing it
+/// checks at compile time that a stop handles the whole group. `#[bus_stop]` (with the `macros` feature) implements
+/// `Handles` for every event it handles, otherwise it must be implemented by hand
+///
+/// ```rust
+/// use dabus::{events, event::Handles, BusInterface, BusStop, EventRegister};
+///
+/// events! {
+///     /// writing text somewhere
+///     pub trait Printing;
+///
+///     /// prints a line
+///     pub PRINT_EVENT: String => ();
+///     /// makes sure everything printed so far is written
+///     pub FLUSH_EVENT: () => ();
+/// }
+///
+/// #[derive(Debug)]
+/// struct Printer;
+///
+/// impl Printer {
+///     async fn print(&mut self, line: String, _i: BusInterface) {
+///         println!("{line}");
+///     }
+///
+///     async fn flush(&mut self, _: (), _i: BusInterface) {}
+/// }
+///
+/// impl BusStop for Printer {
+///     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+///         h.handler(PRINT_EVENT, Self::print)
+///             .handler(FLUSH_EVENT, Self::flush)
+///     }
+/// }
+///
+/// impl Handles<PRINT_EVENT> for Printer {}
+/// impl Handles<FLUSH_EVENT> for Printer {}
+/// impl Printing for Printer {}
+/// ```
+///
+/// since the marker trait requires every event in the group, a `#[cfg]` that removes one of them must also be on
+/// the marker trait
+///
+/// like with [`event!`], events in a group without a visibility are `pub`
+#[macro_export]
+macro_rules! events {
+    (
+        $(#[$trait_attr:meta])* $trait_vis:vis trait $trait:ident;
+        $($items:tt)*
+    ) => {
+        $crate::events!(@items [$(#[$trait_attr])* $trait_vis trait $trait;] [] $($items)*);
+    };
+    // gives the events without a visibility `pub`, like `event!` does, one at a time
+    (@items $trait:tt [$($done:tt)*] $(#[$attr:meta])* $name:ident: $arg:ty => $ret:ty; $($rest:tt)*) => {
+        $crate::events!(@items $trait [$($done)* { $(#[$attr])* pub $name: $arg => $ret }] $($rest)*);
+    };
+    (@items $trait:tt [$($done:tt)*] $(#[$attr:meta])* $vis:vis $name:ident: $arg:ty => $ret:ty; $($rest:tt)*) => {
+        $crate::events!(@items $trait [$($done)* { $(#[$attr])* $vis $name: $arg => $ret }] $($rest)*);
+    };
+    (
+        @items [$(#[$trait_attr:meta])* $trait_vis:vis trait $trait:ident;]
+        [$({ $(#[$attr:meta])* $vis:vis $name:ident: $arg:ty => $ret:ty })*]
+    ) => {
+        $($crate::event!(@declare $(#[$attr])* $vis $name, $arg, $ret);)*
+
+        $(#[$trait_attr])*
+        $trait_vis trait $trait: $crate::BusStop $(+ $crate::event::Handles<$name>)* {}
+    };
+}
+
+/// declares a protocol: a group of events (like [`events!`]), along with a typed client for firing them
+///
+/// the client wraps a [`Caller`](crate::event::protocol::Caller) (such as a [`DABus`](crate::DABus) or
+/// [`BusInterface`](crate::BusInterface)), and has a method for each event, so callers can write `kv.get(key).await`
+/// instead of `i.fire(KV_GET, key).await`. the client type also implements
+/// [`Protocol`](crate::event::protocol::Protocol), so that [`EventRegister::requires_protocol`] and
+/// [`DABus::verify_protocol`] can check that every event of the protocol is handled
+///
+/// ```rust
+/// use dabus::{protocol, BusInterface, BusStop, DABus, EventRegister};
+/// # use std::collections::HashMap;
+///
+/// protocol! {
+///     /// a key value store
+///     pub struct KeyValue;
+///
+///     /// gets the value of a key
+///     pub fn get(KV_GET): String => Option<String>;
+///     /// sets the value of a key
+///     pub fn set(KV_SET): (String, String) => ();
+///     /// removes a key (attributes such as `#[cfg]` apply to the event and the method)
+///     #[cfg(feature = "kv_remove")]
+///     pub fn remove(KV_REMOVE): String => ();
+/// }
+///
+/// #[derive(Debug, Default)]
+/// struct Store(HashMap<String, String>);
+///
+/// impl Store {
+///     async fn get(&mut self, key: String, _i: BusInterface) -> Option<String> {
+///         self.0.get(&key).cloned()
+///     }
+///
+///     async fn set(&mut self, (key, value): (String, String), _i: BusInterface) {
+///         self.0.insert(key, value);
+///     }
+/// }
+///
+/// impl BusStop for Store {
+///     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+///         h.handler(KV_GET, Self::get).handler(KV_SET, Self::set)
+///     }
+/// }
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let mut bus = DABus::new();
+/// assert!(bus.verify_protocol::<KeyValue>().is_err());
+/// bus.register(Store::default());
+/// bus.verify_protocol::<KeyValue>().unwrap();
+///
+/// let mut kv = KeyValue(&mut bus);
+/// kv.set((String::from("key"), String::from("value"))).await.unwrap();
+/// assert_eq!(kv.get(String::from("key")).await.unwrap().as_deref(), Some("value"));
+/// # });
+/// ```
+///
+/// each method fires its event with [`Caller::call`](crate::event::protocol::Caller::call), and has the visibility
+/// and attributes of the event. like with [`event!`], events (and their methods) without a visibility are `pub`
+///
+/// [`EventRegister::requires_protocol`]: crate::EventRegister::requires_protocol
+/// [`DABus::verify_protocol`]: crate::DABus::verify_protocol
+#[macro_export]
+macro_rules! protocol {
+    (
+        $(#[$proto_attr:meta])* $proto_vis:vis struct $proto:ident;
+        $($items:tt)*
+    ) => {
+        $crate::protocol!(@items [$(#[$proto_attr])* $proto_vis struct $proto;] [] $($items)*);
+    };
+    // gives the methods without a visibility `pub`, like `event!` does, one at a time
+    (@items $proto:tt [$($done:tt)*] $(#[$attr:meta])* fn $method:ident($name:ident): $arg:ty => $ret:ty; $($rest:tt)*) => {
+        $crate::protocol!(@items $proto [$($done)* { $(#[$attr])* pub fn $method($name): $arg => $ret }] $($rest)*);
+    };
+    (@items $proto:tt [$($done:tt)*] $(#[$attr:meta])* $vis:vis fn $method:ident($name:ident): $arg:ty => $ret:ty; $($rest:tt)*) => {
+        $crate::protocol!(@items $proto [$($done)* { $(#[$attr])* $vis fn $method($name): $arg => $ret }] $($rest)*);
+    };
+    (
+        @items [$(#[$proto_attr:meta])* $proto_vis:vis struct $proto:ident;]
+        [$({ $(#[$attr:meta])* $vis:vis fn $method:ident($name:ident): $arg:ty => $ret:ty })*]
+    ) => {
+        $($crate::event!(@declare $(#[$attr])* $vis $name, $arg, $ret);)*
+
+        $(#[$proto_attr])*
+        $proto_vis struct $proto<C = ()>(pub C);
+
+        impl<C: $crate::event::protocol::Caller> $proto<C> {
+            $(
+                $(#[$attr])*
+                #[allow(clippy::missing_errors_doc)]
+                $vis async fn $method(
+                    &mut self,
+                    args: $arg,
+                ) -> ::core::result::Result<$ret, $crate::bus::error::CallTrace> {
+                    $crate::event::protocol::Caller::call(&mut self.0, $name, args).await
+                }
+            )*
+        }
+
+        impl<C> $crate::event::protocol::Protocol for $proto<C> {
+            const NAME: &'static str = stringify!($proto);
+
+            fn events() -> ::std::vec::Vec<(::core::any::TypeId, &'static str)> {
+                ::std::vec![$(
+                    $(#[$attr])*
+                    (::core::any::TypeId::of::<$name>(), stringify!($name)),
+                )*]
+            }
+        }
     };
 }
@@ -1,14 +1,21 @@
-use std::{any::TypeId, fmt::Debug, sync::Arc};
+use std::{
+    any::{type_name, TypeId},
+    fmt::Debug,
+    sync::Arc,
+};
 
-use futures::lock::Mutex;
+use async_lock::RwLock;
 
 use crate::{
+    bus::error::{BaseFireEventError, FireEventError},
     core::dyn_var::DynVar,
-    event::EventRegister,
+    event::{async_fn_ptr::HandlerOutput, EventRegister},
     interface::BusInterface,
-    util::{dyn_debug::DynDebug, GeneralRequirements},
+    util::GeneralRequirements,
 };
 
+pub mod fn_stop;
+
 #[allow(clippy::module_name_repetitions)]
 pub trait BusStop {
     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self>;
@@ -25,8 +32,20 @@ pub trait BusStopMech: Sized + seal::Sealed {
         event_tag_id: TypeId,
         event: DynVar,
         interface: BusInterface,
-    ) -> (Self, DynVar);
-    fn relevant(&self, event_tag_id: TypeId) -> bool;
+    ) -> (Self, HandlerOutput);
+    /// runs a handler registered with [`EventRegister::shared_handler`]
+    async unsafe fn handle_shared_event(
+        &self,
+        event_tag_id: TypeId,
+        event: DynVar, /* must be the hidden event type */
+        interface: BusInterface,
+    ) -> HandlerOutput;
+    /// the tag type ids of all events this stop has handlers for
+    fn handled_events() -> Vec<TypeId>;
+    /// the tag type ids of the events this stop has shared handlers for
+    fn shared_events() -> Vec<TypeId>;
+    /// the tag type ids and names of all events this stop requires, see [`EventRegister::requires`]
+    fn required_events() -> Vec<(TypeId, &'static str)>;
 }
 
 impl<T> seal::Sealed for T where T: BusStop + Debug + Sized + Send + Sync + 'static {}
@@ -41,7 +60,7 @@ where
         event_tag_id: TypeId,
         event: DynVar, /* must be the hidden event type */
         interface: BusInterface,
-    ) -> (Self, DynVar) /* the hidden return type */ {
+    ) -> (Self, HandlerOutput) /* the hidden return type */ {
         // TODO make this not query handlers each and every event
         let mut handlers = T::registered_handlers(EventRegister::new())
             .handlers
@@ -61,15 +80,43 @@ where
         (typed_self, res)
     }
 
-    fn relevant(&self, event_tag_id: TypeId) -> bool {
-        // TODO make this not query handlers each and every event
-        let handlers = T::registered_handlers(EventRegister::new())
-            .handlers
+    async unsafe fn handle_shared_event(
+        &self,
+        event_tag_id: TypeId,
+        event: DynVar, /* must be the hidden event type */
+        interface: BusInterface,
+    ) -> HandlerOutput {
+        let mut handlers = T::registered_handlers(EventRegister::new())
+            .shared_handlers
             .into_iter()
             .filter(|rh| rh.0 == event_tag_id)
             .collect::<Vec<_>>();
-        debug_assert!(handlers.len() <= 1);
-        !handlers.is_empty()
+        debug_assert!(handlers.len() == 1);
+        let handler = handlers.remove(0);
+
+        handler.1.call(self, event, interface).await
+    }
+
+    fn handled_events() -> Vec<TypeId> {
+        let register = T::registered_handlers(EventRegister::new());
+        register
+            .handlers
+            .into_iter()
+            .map(|rh| rh.0)
+            .chain(register.shared_handlers.into_iter().map(|rh| rh.0))
+            .collect()
+    }
+
+    fn shared_events() -> Vec<TypeId> {
+        T::registered_handlers(EventRegister::new())
+            .shared_handlers
+            .into_iter()
+            .map(|rh| rh.0)
+            .collect()
+    }
+
+    fn required_events() -> Vec<(TypeId, &'static str)> {
+        T::registered_handlers(EventRegister::new()).required
     }
 }
 
@@ -88,8 +135,13 @@ impl<B: BusStopMech + GeneralRequirements + Send + Sync + 'static> BusStopMechCo
         event_tag_id: TypeId,
         event: DynVar, /* must be the hidden event type */
         interface: BusInterface,
-    ) -> DynVar {
-        let moved_self = self.inner.take().unwrap();
+    ) -> HandlerOutput {
+        let Some(moved_self) = self.inner.take() else {
+            // the stop was dropped along with an unfinished handler (see `BusInterface::fwd_bus_err`)
+            return HandlerOutput::BusError(FireEventError::from(
+                BaseFireEventError::RuntimeInvariant("the stop was lost by a previous handler"),
+            ));
+        };
         let (moved_self, res) = moved_self
             .handle_raw_event(event_tag_id, event, interface)
             .await;
@@ -97,12 +149,48 @@ impl<B: BusStopMech + GeneralRequirements + Send + Sync + 'static> BusStopMechCo
         res
     }
 
-    pub fn relevant(&mut self, event_tag_id: TypeId) -> bool {
-        self.inner.as_mut().unwrap().relevant(event_tag_id)
+    pub async unsafe fn handle_shared_event(
+        &self,
+        event_tag_id: TypeId,
+        event: DynVar, /* must be the hidden event type */
+        interface: BusInterface,
+    ) -> HandlerOutput {
+        let Some(inner) = &self.inner else {
+            return HandlerOutput::BusError(FireEventError::from(
+                BaseFireEventError::RuntimeInvariant("the stop was lost by a previous handler"),
+            ));
+        };
+        inner
+            .handle_shared_event(event_tag_id, event, interface)
+            .await
+    }
+
+    /// the contained stop, unless it was lost by a handler
+    pub fn into_inner(self) -> Option<B> {
+        self.inner
+    }
+
+    pub fn handled_events(&self) -> Vec<TypeId> {
+        B::handled_events()
+    }
+
+    pub fn shared_events(&self) -> Vec<TypeId> {
+        B::shared_events()
+    }
+
+    pub fn required_events(&self) -> Vec<(TypeId, &'static str)> {
+        B::required_events()
     }
 
     pub fn debug(&self) -> &dyn Debug {
-        self.inner.as_dbg()
+        match &self.inner {
+            Some(inner) => inner.as_dbg(),
+            None => &"<lost>",
+        }
+    }
+
+    pub fn stop_name(&self) -> &'static str {
+        type_name::<B>()
     }
 }
 
@@ -111,6 +199,14 @@ impl<B: BusStopMech + GeneralRequirements + Send + Sync + 'static> seal::Sealed
 {
 }
 
+impl<B: BusStopMech + GeneralRequirements + Send + Sync + 'static> Debug
+    for BusStopMechContainer<B>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.debug().fmt(f)
+    }
+}
+
 #[async_trait]
 #[doc(hidden)]
 pub trait DynBusStopContainer: seal::Sealed {
@@ -119,9 +215,18 @@ pub trait DynBusStopContainer: seal::Sealed {
         event_tag_id: TypeId,
         event: DynVar, /* must be the hidden event type */
         interface: BusInterface,
-    ) -> DynVar;
-    fn relevant(&mut self, event_tag_id: TypeId) -> bool;
+    ) -> HandlerOutput;
+    async unsafe fn handle_shared_event(
+        &self,
+        event_tag_id: TypeId,
+        event: DynVar, /* must be the hidden event type */
+        interface: BusInterface,
+    ) -> HandlerOutput;
+    fn handled_events(&self) -> Vec<TypeId>;
+    fn shared_events(&self) -> Vec<TypeId>;
+    fn required_events(&self) -> Vec<(TypeId, &'static str)>;
     fn debug(&self) -> &dyn Debug;
+    fn stop_name(&self) -> &'static str;
 }
 
 #[async_trait]
@@ -133,62 +238,137 @@ impl<B: BusStopMech + GeneralRequirements + Send + Sync + 'static> DynBusStopCon
         event_tag_id: TypeId,
         event: DynVar, /* must be the hidden event type */
         interface: BusInterface,
-    ) -> DynVar {
+    ) -> HandlerOutput {
         Self::handle_raw_event(self, event_tag_id, event, interface).await
     }
 
-    fn relevant(&mut self, event_tag_id: TypeId) -> bool {
-        Self::relevant(self, event_tag_id)
+    async unsafe fn handle_shared_event(
+        &self,
+        event_tag_id: TypeId,
+        event: DynVar, /* must be the hidden event type */
+        interface: BusInterface,
+    ) -> HandlerOutput {
+        Self::handle_shared_event(self, event_tag_id, event, interface).await
+    }
+
+    fn handled_events(&self) -> Vec<TypeId> {
+        self.handled_events()
+    }
+
+    fn shared_events(&self) -> Vec<TypeId> {
+        self.shared_events()
+    }
+
+    fn required_events(&self) -> Vec<(TypeId, &'static str)> {
+        self.required_events()
     }
 
     fn debug(&self) -> &dyn Debug {
         self.debug()
     }
+
+    fn stop_name(&self) -> &'static str {
+        self.stop_name()
+    }
 }
 
 pub trait BusStopReq: DynBusStopContainer + GeneralRequirements {}
 impl<T: DynBusStopContainer + GeneralRequirements> BusStopReq for T {}
 
+/// identifies a registered stop on its bus
+pub type StopId = usize;
+
+/// a registered stop, which is written to by its handlers, or read by any number of shared handlers at once
 pub struct BusStopContainer {
-    pub inner: Mutex<Box<dyn BusStopReq + Send + Sync + 'static>>,
+    pub inner: RwLock<Box<dyn BusStopReq + Send + Sync + 'static>>,
+    name: &'static str,
+    id: StopId,
+    /// cached so that finding handlers does not need to lock the stop
+    handled: Vec<TypeId>,
+    shared: Vec<TypeId>,
+    required: Vec<(TypeId, &'static str)>,
 }
 
 impl BusStopContainer {
-    pub fn new(inner: Box<dyn BusStopReq + Send + Sync + 'static>) -> Self {
+    pub fn new(inner: Box<dyn BusStopReq + Send + Sync + 'static>, id: StopId) -> Self {
         Self {
-            inner: Mutex::new(inner),
+            name: inner.stop_name(),
+            id,
+            handled: inner.handled_events(),
+            shared: inner.shared_events(),
+            required: inner.required_events(),
+            inner: RwLock::new(inner),
         }
     }
 
+    /// the type name of the contained stop
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
     pub async unsafe fn handle_raw_event(
         self: Arc<Self>,
         event_tag_id: TypeId,
         event: DynVar, /* must be the hidden event type */
         interface: BusInterface,
-    ) -> DynVar {
-        let ret = self
-            .inner
-            .try_lock()
-            .unwrap()
-            .handle_raw_event(event_tag_id, event, interface)
-            .await;
-        ret
+    ) -> HandlerOutput {
+        let Some(mut inner) = self.inner.try_write() else {
+            return HandlerOutput::BusError(FireEventError::from(BaseFireEventError::HandlerBusy));
+        };
+        inner.handle_raw_event(event_tag_id, event, interface).await
+    }
+
+    /// runs a shared handler, alongside any others that are already running
+    pub async unsafe fn handle_shared_event(
+        self: Arc<Self>,
+        event_tag_id: TypeId,
+        event: DynVar, /* must be the hidden event type */
+        interface: BusInterface,
+    ) -> HandlerOutput {
+        let Some(inner) = self.inner.try_read() else {
+            return HandlerOutput::BusError(FireEventError::from(BaseFireEventError::HandlerBusy));
+        };
+        inner
+            .handle_shared_event(event_tag_id, event, interface)
+            .await
+    }
+
+    pub fn relevant(&self, event_tag_id: TypeId) -> bool {
+        self.handled.contains(&event_tag_id)
+    }
+
+    /// if the handler for `event_tag_id` is a shared handler
+    pub fn is_shared(&self, event_tag_id: TypeId) -> bool {
+        self.shared.contains(&event_tag_id)
+    }
+
+    pub const fn id(&self) -> StopId {
+        self.id
+    }
+
+    /// the events this stop handles
+    pub fn handled(&self) -> &[TypeId] {
+        &self.handled
     }
 
-    pub fn relevant(&mut self, event_tag_id: TypeId) -> bool {
-        self.inner.try_lock().unwrap().relevant(event_tag_id)
+    /// the events this stop fires, see [`EventRegister::requires`]
+    pub fn required(&self) -> &[(TypeId, &'static str)] {
+        &self.required
     }
 
     pub fn debug(&mut self) -> &dyn Debug {
         let i = self.inner.get_mut();
-        (**i).as_dbg()
+        (**i).debug()
     }
 }
 
 impl Debug for BusStopContainer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("BusStopContainer")
-            .field("inner", self.inner.try_lock().unwrap().as_dbg())
-            .finish()
+        let mut s = f.debug_struct("BusStopContainer");
+        match self.inner.try_read() {
+            Some(inner) => s.field("inner", inner.debug()),
+            None => s.field("inner", &format_args!("<busy>")),
+        };
+        s.finish()
     }
 }
@@ -0,0 +1,430 @@
+//! stops made of closures, for when a whole struct is not worth it
+
+use std::{
+    any::TypeId,
+    error::Error,
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    marker::PhantomData,
+};
+
+use futures::future::BoxFuture;
+
+use super::{seal, DynBusStopContainer};
+use crate::{
+    bus::error::{BaseFireEventError, CallTrace, FireEventError, HandlerError},
+    core::dyn_var::DynVar,
+    event::{async_fn_ptr::HandlerOutput, EventDef},
+    interface::BusInterface,
+    unique_type,
+    util::dyn_debug::DynDebug,
+};
+
+/// A stop made of closures, registered with [`DABus::register_fn_stop`]
+///
+/// like any other stop, only one of its handlers runs at a time. closures that need to share state can keep it in
+/// the stop (see [`FnStop::with_state`]), or each capture a clone of an `Arc`
+///
+/// ```rust
+/// use std::sync::{
+///     atomic::{AtomicU32, Ordering},
+///     Arc,
+/// };
+/// use dabus::{event, DABus, FnStop};
+///
+/// event!(INCREMENT, (), ());
+/// event!(COUNT, (), u32);
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let count = Arc::new(AtomicU32::new(0));
+/// let mut bus = DABus::new();
+/// let handle = bus.register_fn_stop(
+///     FnStop::new("counter")
+///         .handler(INCREMENT, {
+///             let count = count.clone();
+///             move |(), _i| {
+///                 count.fetch_add(1, Ordering::Relaxed);
+///                 async {}
+///             }
+///         })
+///         .handler(COUNT, move |(), _i| {
+///             let count = count.load(Ordering::Relaxed);
+///             async move { count }
+///         }),
+/// );
+///
+/// bus.fire(INCREMENT, ()).await.unwrap();
+/// assert_eq!(bus.fire(COUNT, ()).await.unwrap().ret(), 1);
+///
+/// assert!(bus.remove(handle));
+/// assert!(!bus.has_handler(COUNT));
+/// # });
+/// ```
+///
+/// [`DABus::register_fn_stop`]: crate::DABus::register_fn_stop
+pub struct FnStop<S = ()> {
+    name: &'static str,
+    state: S,
+    handlers: Vec<(
+        TypeId,
+        &'static str,
+        Box<dyn FnHandlerErased<S> + Send + Sync>,
+    )>,
+    required: Vec<(TypeId, &'static str)>,
+}
+
+impl FnStop {
+    /// Creates a stop with no handlers, which is called `name` in call traces and metrics
+    #[must_use]
+    pub fn new(name: &'static str) -> Self {
+        Self::with_state(name, ())
+    }
+}
+
+impl<S: Send + Sync + 'static> FnStop<S> {
+    /// Creates a stop with no handlers that keeps `state`, which is lent to handlers added with
+    /// [`FnStop::state_handler`] (and the other `state_` methods)
+    ///
+    /// ```rust
+    /// use dabus::{event, DABus, FnStop};
+    ///
+    /// event!(PUSH, String, ());
+    /// event!(JOINED, (), String);
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let mut bus = DABus::new();
+    /// bus.register_fn_stop(
+    ///     FnStop::with_state("lines", Vec::<String>::new())
+    ///         .state_handler(PUSH, |lines, line, _i| Box::pin(async move { lines.push(line) }))
+    ///         .state_handler(JOINED, |lines, (), _i| Box::pin(async move { lines.join("\n") })),
+    /// );
+    ///
+    /// bus.fire(PUSH, String::from("a")).await.unwrap();
+    /// bus.fire(PUSH, String::from("b")).await.unwrap();
+    /// assert_eq!(bus.fire(JOINED, ()).await.unwrap().ret(), "a\nb");
+    /// # });
+    /// ```
+    #[must_use]
+    pub fn with_state(name: &'static str, state: S) -> Self {
+        Self {
+            name,
+            state,
+            handlers: vec![],
+            required: vec![],
+        }
+    }
+
+    /// adds a handler for `def`, like [`EventRegister::handler`](crate::EventRegister::handler)
+    #[must_use]
+    pub fn handler<Tag, At, Rt, F, Fut>(self, def: &'static EventDef<Tag, At, Rt>, func: F) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        Rt: DynDebug + Send + Sync + 'static,
+        F: Fn(At, BusInterface) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Rt> + Send + 'static,
+    {
+        self.push(def, FnHandler::new(func, return_output))
+    }
+
+    /// adds a handler returning `Result<T, E>`, like [`EventRegister::fallible_handler`]
+    ///
+    /// [`EventRegister::fallible_handler`]: crate::EventRegister::fallible_handler
+    #[must_use]
+    pub fn fallible_handler<Tag, At, T, E, F, Fut>(
+        self,
+        def: &'static EventDef<Tag, At, Result<T, E>>,
+        func: F,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        T: Send + Sync + 'static,
+        E: Error + Send + Sync + 'static,
+        Result<T, E>: DynDebug,
+        F: Fn(At, BusInterface) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, E>> + Send + 'static,
+    {
+        self.push(def, FnHandler::new(func, fallible_output))
+    }
+
+    /// adds a handler returning `Result<Rt, CallTrace>`, like [`EventRegister::forwarding_handler`]
+    ///
+    /// [`EventRegister::forwarding_handler`]: crate::EventRegister::forwarding_handler
+    #[must_use]
+    pub fn forwarding_handler<Tag, At, Rt, F, Fut>(
+        self,
+        def: &'static EventDef<Tag, At, Rt>,
+        func: F,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        Rt: DynDebug + Send + Sync + 'static,
+        F: Fn(At, BusInterface) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Rt, CallTrace>> + Send + 'static,
+    {
+        self.push(def, FnHandler::new(func, forwarding_output))
+    }
+
+    /// adds a handler for `def` that is lent the stop's state (see [`FnStop::with_state`]), like [`FnStop::handler`].
+    ///
+    /// the returned future may borrow the state, so it has to be boxed (with `Box::pin`)
+    #[must_use]
+    pub fn state_handler<Tag, At, Rt, F>(self, def: &'static EventDef<Tag, At, Rt>, func: F) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        Rt: DynDebug + Send + Sync + 'static,
+        F: for<'a> Fn(&'a mut S, At, BusInterface) -> BoxFuture<'a, Rt> + Send + Sync + 'static,
+    {
+        self.push(def, StateFnHandler::new(func, return_output))
+    }
+
+    /// adds a fallible handler that is lent the stop's state, like [`FnStop::fallible_handler`] and
+    /// [`FnStop::state_handler`]
+    #[must_use]
+    pub fn state_fallible_handler<Tag, At, T, E, F>(
+        self,
+        def: &'static EventDef<Tag, At, Result<T, E>>,
+        func: F,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        T: Send + Sync + 'static,
+        E: Error + Send + Sync + 'static,
+        Result<T, E>: DynDebug,
+        F: for<'a> Fn(&'a mut S, At, BusInterface) -> BoxFuture<'a, Result<T, E>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.push(def, StateFnHandler::new(func, fallible_output))
+    }
+
+    /// adds a forwarding handler that is lent the stop's state, like [`FnStop::forwarding_handler`] and
+    /// [`FnStop::state_handler`]
+    #[must_use]
+    pub fn state_forwarding_handler<Tag, At, Rt, F>(
+        self,
+        def: &'static EventDef<Tag, At, Rt>,
+        func: F,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+        At: DynDebug + Send + Sync + 'static,
+        Rt: DynDebug + Send + Sync + 'static,
+        F: for<'a> Fn(&'a mut S, At, BusInterface) -> BoxFuture<'a, Result<Rt, CallTrace>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.push(def, StateFnHandler::new(func, forwarding_output))
+    }
+
+    /// declares that this stop fires `def`, like [`EventRegister::requires`](crate::EventRegister::requires)
+    #[must_use]
+    pub fn requires<Tag, At, Rt>(mut self, def: &'static EventDef<Tag, At, Rt>) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+    {
+        self.required.push((TypeId::of::<Tag>(), def.name()));
+        self
+    }
+
+    fn push<Tag, At, Rt>(
+        mut self,
+        def: &'static EventDef<Tag, At, Rt>,
+        handler: impl FnHandlerErased<S> + Send + Sync + 'static,
+    ) -> Self
+    where
+        Tag: unique_type::Unique + 'static,
+    {
+        let tag = TypeId::of::<Tag>();
+        if let Some(index) = self.handlers.iter().position(|(id, ..)| *id == tag) {
+            warn!(
+                "{} already has a handler for {}, replacing it",
+                self.name,
+                def.name()
+            );
+            self.handlers.remove(index);
+        }
+        self.handlers.push((tag, def.name(), Box::new(handler)));
+        self
+    }
+}
+
+fn return_output<Rt: DynDebug + Send + Sync + 'static>(r: Rt) -> HandlerOutput {
+    HandlerOutput::Return(DynVar::new(r))
+}
+
+fn fallible_output<T, E>(r: Result<T, E>) -> HandlerOutput
+where
+    T: Send + Sync + 'static,
+    E: Error + Send + Sync + 'static,
+    Result<T, E>: DynDebug,
+{
+    match &r {
+        Ok(..) => HandlerOutput::Return(DynVar::new(r)),
+        Err(err) => {
+            let err = HandlerError::new(err);
+            HandlerOutput::Failed(DynVar::new(r), err)
+        }
+    }
+}
+
+fn forwarding_output<Rt: DynDebug + Send + Sync + 'static>(
+    r: Result<Rt, CallTrace>,
+) -> HandlerOutput {
+    match r {
+        Ok(r) => HandlerOutput::Return(DynVar::new(r)),
+        Err(trace) => HandlerOutput::Forward(Box::new(trace)),
+    }
+}
+
+impl<S> Debug for FnStop<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FnStop")
+            .field("name", &self.name)
+            .field(
+                "handlers",
+                &self.handlers.iter().map(|h| h.1).collect::<Vec<_>>(),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> seal::Sealed for FnStop<S> {}
+
+#[async_trait]
+impl<S: Send + Sync + 'static> DynBusStopContainer for FnStop<S> {
+    async unsafe fn handle_raw_event(
+        &mut self,
+        event_tag_id: TypeId,
+        event: DynVar, /* must be the hidden event type */
+        interface: BusInterface,
+    ) -> HandlerOutput {
+        let Some((.., handler)) = self.handlers.iter().find(|h| h.0 == event_tag_id) else {
+            return HandlerOutput::BusError(FireEventError::from(
+                BaseFireEventError::RuntimeInvariant(
+                    "a closure stop was called for an event it does not handle",
+                ),
+            ));
+        };
+        handler.call(&mut self.state, event, interface).await
+    }
+
+    async unsafe fn handle_shared_event(
+        &self,
+        _event_tag_id: TypeId,
+        _event: DynVar,
+        _interface: BusInterface,
+    ) -> HandlerOutput {
+        HandlerOutput::BusError(FireEventError::from(BaseFireEventError::RuntimeInvariant(
+            "closure stops do not have shared handlers",
+        )))
+    }
+
+    fn handled_events(&self) -> Vec<TypeId> {
+        self.handlers.iter().map(|h| h.0).collect()
+    }
+
+    fn shared_events(&self) -> Vec<TypeId> {
+        vec![]
+    }
+
+    fn required_events(&self) -> Vec<(TypeId, &'static str)> {
+        self.required.clone()
+    }
+
+    fn debug(&self) -> &dyn Debug {
+        self
+    }
+
+    fn stop_name(&self) -> &'static str {
+        self.name
+    }
+}
+
+trait FnHandlerErased<S> {
+    /// # Safety
+    ///
+    /// `a` must have the argument type of the handler's event
+    unsafe fn call<'a>(
+        &'a self,
+        state: &'a mut S,
+        a: DynVar,
+        i: BusInterface,
+    ) -> BoxFuture<'a, HandlerOutput>;
+}
+
+struct FnHandler<F, At, Rt> {
+    f: F,
+    output: fn(Rt) -> HandlerOutput,
+    _t: PhantomData<fn(At) -> Rt>,
+}
+
+impl<F, At, Rt> FnHandler<F, At, Rt> {
+    fn new(f: F, output: fn(Rt) -> HandlerOutput) -> Self {
+        Self {
+            f,
+            output,
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<S, F, Fut, At, Rt> FnHandlerErased<S> for FnHandler<F, At, Rt>
+where
+    F: Fn(At, BusInterface) -> Fut,
+    Fut: Future<Output = Rt> + Send + 'static,
+    At: DynDebug + Send + Sync + 'static,
+    Rt: 'static,
+{
+    unsafe fn call<'a>(
+        &'a self,
+        _: &'a mut S,
+        a: DynVar,
+        i: BusInterface,
+    ) -> BoxFuture<'a, HandlerOutput> {
+        let fut = (self.f)(a.try_to_unchecked::<At>(), i);
+        let output = self.output;
+        Box::pin(async move { output(fut.await) })
+    }
+}
+
+/// a handler that is lent the state of its stop
+struct StateFnHandler<F, At, Rt> {
+    f: F,
+    output: fn(Rt) -> HandlerOutput,
+    _t: PhantomData<fn(At) -> Rt>,
+}
+
+impl<F, At, Rt> StateFnHandler<F, At, Rt> {
+    fn new(f: F, output: fn(Rt) -> HandlerOutput) -> Self {
+        Self {
+            f,
+            output,
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<S, F, At, Rt> FnHandlerErased<S> for StateFnHandler<F, At, Rt>
+where
+    F: for<'a> Fn(&'a mut S, At, BusInterface) -> BoxFuture<'a, Rt>,
+    At: DynDebug + Send + Sync + 'static,
+    Rt: 'static,
+{
+    unsafe fn call<'a>(
+        &'a self,
+        state: &'a mut S,
+        a: DynVar,
+        i: BusInterface,
+    ) -> BoxFuture<'a, HandlerOutput> {
+        let fut = (self.f)(state, a.try_to_unchecked::<At>(), i);
+        let output = self.output;
+        Box::pin(async move { output(fut.await) })
+    }
+}
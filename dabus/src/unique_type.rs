@@ -1 +1,12 @@
+use crate::event::Event;
+
+/// marker for the hidden tag types that identify events
+///
+/// # Safety
+///
+/// each implementing type must be used as the tag of exactly one [`EventDef`](crate::EventDef),
+/// as the `TypeId` of the tag is what events are dispatched by. use `event!` or [`Event`] instead of implementing this by hand
 pub unsafe trait Unique {}
+
+// an event type is the tag of its own `Event::DEF`, and nothing else
+unsafe impl<E: Event> Unique for E {}
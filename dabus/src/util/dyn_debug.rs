@@ -1,23 +1,22 @@
-use core::fmt::{self, Debug, Formatter};
+//! debug-formatting values whose type is not known
+//!
+//! with the `nightly` feature, [`DynDebug`] is implemented for every type, and types that are not [`Debug`] are
+//! formatted as `{dyn DynDebug}`. otherwise it is only implemented for [`Debug`] types, so event arguments, return
+//! values and stops must be [`Debug`] (types that can not be, can be wrapped in a newtype with a manual impl)
 
-struct DefaultDbg;
+use core::fmt::Debug;
 
-impl Debug for DefaultDbg {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("{dyn DynDebug}").finish()
-    }
-}
+// in a module of its own, so that stable compilers never parse it
+#[cfg(feature = "nightly")]
+mod fallback;
 
+#[diagnostic::on_unimplemented(
+    note = "without the `nightly` feature, values passed through the bus must implement `Debug`"
+)]
 pub trait DynDebug {
     fn as_dbg(&self) -> &dyn Debug;
 }
 
-impl<T> DynDebug for T {
-    default fn as_dbg(&self) -> &dyn Debug {
-        &DefaultDbg
-    }
-}
-
 impl<T: Debug> DynDebug for T {
     fn as_dbg(&self) -> &dyn Debug {
         self
@@ -0,0 +1,17 @@
+use core::fmt::{self, Debug, Formatter};
+
+use super::DynDebug;
+
+struct DefaultDbg;
+
+impl Debug for DefaultDbg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("{dyn DynDebug}").finish()
+    }
+}
+
+impl<T> DynDebug for T {
+    default fn as_dbg(&self) -> &dyn Debug {
+        &DefaultDbg
+    }
+}
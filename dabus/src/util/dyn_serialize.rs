@@ -0,0 +1,27 @@
+use std::io;
+
+// in a module of its own, so that stable compilers never parse it
+#[cfg(feature = "nightly")]
+mod specialized;
+
+/// Allows serializing types that may or may not be [`Serialize`], the same way [`DynDebug`] does for [`Debug`]
+///
+/// this needs the `nightly` feature. without it, no values are serialized (and are captured as
+/// [`Captured::Unserializable`](crate::bus::capture::Captured::Unserializable))
+///
+/// [`Serialize`]: serde::Serialize
+/// [`DynDebug`]: super::dyn_debug::DynDebug
+/// [`Debug`]: core::fmt::Debug
+pub trait DynSerialize {
+    /// serializes `self` as JSON into `writer`, or returns `None` if `Self` is not [`Serialize`]
+    ///
+    /// [`Serialize`]: serde::Serialize
+    fn serialize_json(&self, writer: &mut dyn io::Write) -> Option<serde_json::Result<()>>;
+}
+
+#[cfg(not(feature = "nightly"))]
+impl<T> DynSerialize for T {
+    fn serialize_json(&self, _: &mut dyn io::Write) -> Option<serde_json::Result<()>> {
+        None
+    }
+}
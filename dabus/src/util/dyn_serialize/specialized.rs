@@ -0,0 +1,17 @@
+use std::io;
+
+use serde::Serialize;
+
+use super::DynSerialize;
+
+impl<T> DynSerialize for T {
+    default fn serialize_json(&self, _: &mut dyn io::Write) -> Option<serde_json::Result<()>> {
+        None
+    }
+}
+
+impl<T: Serialize> DynSerialize for T {
+    fn serialize_json(&self, writer: &mut dyn io::Write) -> Option<serde_json::Result<()>> {
+        Some(serde_json::to_writer(writer, self))
+    }
+}
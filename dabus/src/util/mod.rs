@@ -7,16 +7,34 @@
 pub mod async_util;
 pub mod dyn_debug;
 pub mod dyn_downcast;
+#[cfg(feature = "backtrace_serde")]
+pub mod dyn_serialize;
 pub mod dyn_typename;
+#[cfg(feature = "nightly")]
 pub mod possibly_clone;
 
-// all of these traits are implemeted for any T, so you dont have to explicitly require them
+// all of these traits are implemeted for any T (that is `Debug`, for `DynDebug` without the `nightly` feature),
+// so you dont have to explicitly require them
 pub use dyn_downcast::AsAny;
 pub use dyn_typename::TypeNamed;
+#[cfg(feature = "nightly")]
 pub use possibly_clone::PossiblyClone;
 
 use self::dyn_debug::DynDebug;
 
 /// convenience trait for [`TypeNamed`] + [`AsAny`] + 'static
+#[cfg(not(feature = "backtrace_serde"))]
 pub trait GeneralRequirements: DynDebug + TypeNamed + AsAny + 'static {}
+#[cfg(not(feature = "backtrace_serde"))]
+impl<T: DynDebug + 'static> GeneralRequirements for T {}
+
+/// convenience trait for [`TypeNamed`] + [`AsAny`] + [`DynSerialize`] + 'static
+///
+/// [`DynSerialize`]: dyn_serialize::DynSerialize
+#[cfg(feature = "backtrace_serde")]
+pub trait GeneralRequirements:
+    DynDebug + dyn_serialize::DynSerialize + TypeNamed + AsAny + 'static
+{
+}
+#[cfg(feature = "backtrace_serde")]
 impl<T: DynDebug + 'static> GeneralRequirements for T {}
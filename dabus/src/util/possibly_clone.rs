@@ -1,4 +1,4 @@
-// if anyone is wondering, this is why the crate requires #![feature(specialization)]
+// if anyone is wondering, this is why the crate used to require #![feature(specialization)] (now the `nightly` feature)
 
 /// Allows for cloning types that may or may not be [`Clone`]
 ///
@@ -0,0 +1,34 @@
+use std::{collections::BTreeSet, thread};
+
+use dabus::{bus::blocking::ThreadSpawner, event, BusStop, DABus, EventRegister};
+
+event!(THREAD_NAME, (), Option<String>);
+
+#[derive(Debug)]
+struct Named;
+
+impl Named {
+    fn thread_name(&mut self, (): ()) -> Option<String> {
+        thread::current().name().map(String::from)
+    }
+}
+
+impl BusStop for Named {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.blocking_handler(THREAD_NAME, Self::thread_name)
+    }
+}
+
+#[test]
+fn blocking_calls_reuse_the_default_pool() {
+    let mut bus = DABus::new();
+    bus.register(Named);
+    let names = (0..64)
+        .map(|_| bus.fire_blocking(THREAD_NAME, ()).unwrap().ret().unwrap())
+        .collect::<BTreeSet<_>>();
+    assert!(names.iter().all(|name| name.starts_with("dabus-blocking-")));
+    let threads = thread::available_parallelism()
+        .map_or(0, usize::from)
+        .max(ThreadSpawner::MIN_THREADS);
+    assert!(names.len() <= threads);
+}
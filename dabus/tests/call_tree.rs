@@ -0,0 +1,170 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{bus_error, Outcome};
+use dabus::{bus::error::BaseFireEventError, event, BusInterface, BusStop, DABus, EventRegister};
+
+event!(ECHO_ALL, Vec<u32>, Vec<u32>);
+event!(SLOW_ECHO, u32, u32);
+
+#[derive(Debug)]
+struct Caller;
+
+impl Caller {
+    async fn echo_all(&mut self, args: Vec<u32>, mut i: BusInterface) -> Vec<u32> {
+        i.fire_all(SLOW_ECHO, args)
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect()
+    }
+}
+
+impl BusStop for Caller {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(ECHO_ALL, Self::echo_all)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Echo {
+    calls: u32,
+}
+
+impl Echo {
+    async fn slow_echo(&mut self, value: u32, _i: BusInterface) -> u32 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        self.calls += 1;
+        value
+    }
+}
+
+impl BusStop for Echo {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(SLOW_ECHO, Self::slow_echo)
+    }
+}
+
+#[tokio::test]
+async fn nested_calls_to_a_busy_stop_are_queued() {
+    let mut bus = DABus::new();
+    bus.register(Caller);
+    bus.register(Echo::default());
+    let result = bus.fire(ECHO_ALL, vec![1, 2, 3]).await.unwrap();
+    let trace = result.trace();
+    assert_eq!(result.ret(), vec![1, 2, 3]);
+    assert_eq!(trace.root.unwrap().inner.len(), 3);
+    assert_eq!(bus.deregister::<Echo>()[0].calls, 3);
+}
+
+event!(PING, (), Outcome);
+event!(PONG, (), Outcome);
+
+#[derive(Debug)]
+struct Ping;
+
+impl Ping {
+    async fn ping(&mut self, (): (), mut i: BusInterface) -> Outcome {
+        i.fire(PONG, ()).await.map_err(|trace| bus_error(&trace))?
+    }
+}
+
+impl BusStop for Ping {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(PING, Self::ping)
+    }
+}
+
+#[derive(Debug)]
+struct Pong;
+
+impl Pong {
+    async fn pong(&mut self, (): (), mut i: BusInterface) -> Outcome {
+        i.fire(PING, ()).await.map_err(|trace| bus_error(&trace))?
+    }
+}
+
+impl BusStop for Pong {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(PONG, Self::pong)
+    }
+}
+
+#[tokio::test]
+async fn mutual_recursion_is_handler_busy() {
+    let mut bus = DABus::new();
+    bus.register(Ping);
+    bus.register(Pong);
+    let result = bus.fire(PING, ()).await.unwrap().ret();
+    assert!(matches!(result, Err(BaseFireEventError::HandlerBusy)));
+    // both stops are back on the bus
+    assert_eq!(bus.deregister::<Ping>().len(), 1);
+    assert_eq!(bus.deregister::<Pong>().len(), 1);
+}
+
+event!(BOTH, (), (Outcome, Outcome));
+event!(LEFT, (), Outcome);
+event!(RIGHT, (), Outcome);
+
+#[derive(Debug)]
+struct Both;
+
+impl Both {
+    async fn both(&mut self, (): (), mut i: BusInterface) -> (Outcome, Outcome) {
+        let mut i2 = i.clone();
+        let (left, right) = futures::join!(i.fire(LEFT, ()), i2.fire(RIGHT, ()));
+        (left.unwrap(), right.unwrap())
+    }
+}
+
+impl BusStop for Both {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(BOTH, Self::both)
+    }
+}
+
+#[derive(Debug)]
+struct Left;
+
+impl Left {
+    async fn left(&mut self, (): (), mut i: BusInterface) -> Outcome {
+        // gives `RIGHT` time to queue a call to this stop
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        i.fire(RIGHT, ()).await.map_err(|trace| bus_error(&trace))?
+    }
+}
+
+impl BusStop for Left {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(LEFT, Self::left)
+    }
+}
+
+#[derive(Debug)]
+struct Right;
+
+impl Right {
+    async fn right(&mut self, (): (), mut i: BusInterface) -> Outcome {
+        i.fire(LEFT, ()).await.map_err(|trace| bus_error(&trace))?
+    }
+}
+
+impl BusStop for Right {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(RIGHT, Self::right)
+    }
+}
+
+#[tokio::test]
+async fn calls_that_would_wait_on_a_queued_call_are_handler_busy() {
+    let mut bus = DABus::new();
+    bus.register(Both);
+    bus.register(Left);
+    bus.register(Right);
+    let (left, right) = bus.fire(BOTH, ()).await.unwrap().ret();
+    // `LEFT` calling `RIGHT` would wait on the call `RIGHT` queued for `LEFT`
+    assert!(matches!(left, Err(BaseFireEventError::HandlerBusy)));
+    // the queued call runs once `LEFT` is free, and fails the same way
+    assert!(matches!(right, Err(BaseFireEventError::HandlerBusy)));
+}
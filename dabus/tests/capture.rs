@@ -0,0 +1,156 @@
+#![cfg(feature = "backtrace_serde")]
+
+use dabus::{
+    bus::{
+        capture::{redact, Captured, Redacted, REDACTED},
+        error::{CallEvent, CallTrace},
+    },
+    event, DABus,
+};
+use serde::Serialize;
+use serde_json::json;
+
+#[derive(Debug, Serialize)]
+struct Login {
+    user: String,
+    password: Redacted<String>,
+    #[serde(serialize_with = "redact")]
+    otp: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct Session {
+    id: u64,
+    roles: Vec<&'static str>,
+}
+
+event!(LOGIN, Login, Session);
+
+fn login_bus() -> DABus {
+    let mut bus = DABus::new();
+    bus.capture::<Login>();
+    bus.capture::<Session>();
+    bus.register_fn(LOGIN, |login: Login, _i| async move {
+        assert_eq!(*login.password, "hunter2");
+        Session {
+            id: 7,
+            roles: vec!["admin"],
+        }
+    });
+    bus
+}
+
+fn login() -> Login {
+    Login {
+        user: String::from("ann"),
+        password: Redacted(String::from("hunter2")),
+        otp: 123_456,
+    }
+}
+
+fn root(trace: CallTrace) -> CallEvent {
+    trace.root.unwrap()
+}
+
+#[tokio::test]
+async fn args_and_returns_are_captured_as_json() {
+    let mut bus = login_bus();
+    let root = root(bus.fire(LOGIN, login()).await.unwrap().trace());
+    assert_eq!(
+        root.args_value,
+        Some(Captured::Value(json!({
+            "user": "ann",
+            "password": REDACTED,
+            "otp": REDACTED,
+        })))
+    );
+    assert_eq!(
+        root.return_value,
+        Some(Captured::Value(json!({ "id": 7, "roles": ["admin"] })))
+    );
+}
+
+#[tokio::test]
+async fn serialized_traces_hold_the_captured_values() {
+    let mut bus = login_bus();
+    let trace = bus.fire(LOGIN, login()).await.unwrap().trace();
+    let serialized = serde_json::to_value(&trace).unwrap();
+    let root = &serialized["root"];
+    assert_eq!(root["handler_name"], "LOGIN");
+    assert_eq!(
+        root["args_value"],
+        json!({
+            "kind": "value",
+            "value": { "user": "ann", "password": REDACTED, "otp": REDACTED },
+        })
+    );
+    assert_eq!(root["return_value"]["value"]["id"], 7);
+
+    // `Redacted` also hides the value from the debug formatted args (`backtrace_track_values`), `redact` only
+    // from the captured value
+    assert!(!serialized.to_string().contains("hunter2"));
+    assert!(!root["args_value"].to_string().contains("123456"));
+}
+
+#[tokio::test]
+async fn values_over_the_limit_are_truncated() {
+    let mut bus = login_bus();
+    bus.set_capture_limit(16);
+    let root = root(bus.fire(LOGIN, login()).await.unwrap().trace());
+    assert_eq!(root.args_value, Some(Captured::Truncated(16)));
+    // `{"id":7,"roles":["admin"]}` is longer than 16 bytes too
+    assert_eq!(root.return_value, Some(Captured::Truncated(16)));
+}
+
+#[derive(Debug)]
+struct Opaque;
+
+event!(OPAQUE, Opaque, u32);
+
+#[tokio::test]
+async fn unserializable_values_are_marked() {
+    let mut bus = DABus::new();
+    bus.capture::<u32>();
+    bus.register_fn(OPAQUE, |Opaque, _i| async { 1 });
+    let root = root(bus.fire(OPAQUE, Opaque).await.unwrap().trace());
+    assert_eq!(root.args_value, Some(Captured::Unserializable));
+    assert_eq!(root.return_value, Some(Captured::Value(json!(1))));
+}
+
+#[cfg(not(feature = "nightly"))]
+#[tokio::test]
+async fn unregistered_types_are_not_captured() {
+    let mut bus = DABus::new();
+    bus.register_fn(LOGIN, |_login, _i| async move {
+        Session {
+            id: 7,
+            roles: vec![],
+        }
+    });
+    let root = root(bus.fire(LOGIN, login()).await.unwrap().trace());
+    assert_eq!(root.args_value, Some(Captured::Unserializable));
+    assert_eq!(root.return_value, Some(Captured::Unserializable));
+}
+
+#[cfg(feature = "nightly")]
+#[tokio::test]
+async fn serializable_types_are_captured_without_registering() {
+    let mut bus = DABus::new();
+    bus.register_fn(LOGIN, |_login, _i| async move {
+        Session {
+            id: 7,
+            roles: vec![],
+        }
+    });
+    let root = root(bus.fire(LOGIN, login()).await.unwrap().trace());
+    assert_eq!(
+        root.args_value,
+        Some(Captured::Value(
+            json!({ "user": "ann", "password": REDACTED, "otp": REDACTED })
+        ))
+    );
+    assert_eq!(
+        root.return_value,
+        Some(Captured::Value(json!({ "id": 7, "roles": [] })))
+    );
+}
@@ -0,0 +1,34 @@
+//! helpers shared by the integration tests
+#![allow(dead_code)] // each test only uses some of them
+
+use dabus::{
+    bus::error::{BaseFireEventError, CallTrace, Resolution},
+    event, unique_type, DABus, EventDef, StopHandle,
+};
+
+/// the result of a handler that makes a nested call
+pub type Outcome = Result<(), BaseFireEventError>;
+
+/// the error reported by the runtime for a failed call
+pub fn bus_error(trace: &CallTrace) -> BaseFireEventError {
+    match trace.source().and_then(|event| event.resolution) {
+        Some(Resolution::BusError(err)) => err.base().clone(),
+        other => panic!("expected a bus error, found {other:?}"),
+    }
+}
+
+event!(pub FAN_OUT, u32, Vec<Outcome>);
+
+/// handles [`FAN_OUT`] by calling `target` as many times as asked to at once, returning the error of each call
+pub fn register_fan_out<Tag>(bus: &mut DABus, target: &'static EventDef<Tag, (), ()>) -> StopHandle
+where
+    Tag: unique_type::Unique + Send + Sync + 'static,
+{
+    bus.register_fn(FAN_OUT, move |calls, mut i| async move {
+        i.fire_all(target, (0..calls).map(|_| ()))
+            .await
+            .into_iter()
+            .map(|result| result.map_err(|trace| bus_error(&trace)))
+            .collect()
+    })
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use std::{
+    any::type_name,
+    sync::{Arc, Mutex},
+};
+
+use common::{bus_error, Outcome};
+use dabus::{
+    bus::{
+        error::BaseFireEventError,
+        fallback::{UnhandledEvent, DEAD_LETTER},
+    },
+    event,
+    extras::DynVar,
+    BusInterface, BusStop, DABus, EventRegister,
+};
+
+event!(UNHANDLED, (), u32);
+event!(CALL_UNHANDLED, (), Outcome);
+
+#[tokio::test]
+async fn nested_calls_returning_the_wrong_type_are_type_mismatch() {
+    let mut bus = DABus::new();
+    // returns a `&str` for every event, which is not what `UNHANDLED` returns
+    bus.register_fn(DEAD_LETTER, |_event: UnhandledEvent, _i| async {
+        Some(DynVar::new("not a number"))
+    });
+    bus.register_fn(CALL_UNHANDLED, |(), mut i| async move {
+        i.fire(UNHANDLED, ())
+            .await
+            .map(drop)
+            .map_err(|trace| bus_error(&trace))
+    });
+    let result = bus.fire(CALL_UNHANDLED, ()).await.unwrap().ret();
+    match result {
+        Err(BaseFireEventError::TypeMismatch { expected, found }) => {
+            assert_eq!(expected, type_name::<u32>());
+            assert_eq!(found, type_name::<&str>());
+        }
+        other => panic!("expected a type mismatch, found {other:?}"),
+    }
+}
+
+event!(RECURSE, (), Outcome);
+
+#[derive(Debug)]
+struct Recursive;
+
+impl Recursive {
+    async fn recurse(&mut self, (): (), mut i: BusInterface) -> Outcome {
+        i.fire(RECURSE, ())
+            .await
+            .map_err(|trace| bus_error(&trace))?
+    }
+}
+
+impl BusStop for Recursive {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(RECURSE, Self::recurse)
+    }
+}
+
+#[tokio::test]
+async fn calls_to_the_callers_own_stop_are_handler_busy() {
+    let mut bus = DABus::new();
+    bus.register(Recursive);
+    let result = bus.fire(RECURSE, ()).await.unwrap().ret();
+    assert!(matches!(result, Err(BaseFireEventError::HandlerBusy)));
+}
+
+event!(FORWARD, (), ());
+event!(COUNT, (), u32);
+
+#[derive(Debug, Default)]
+struct Forwarder {
+    calls: u32,
+}
+
+impl Forwarder {
+    async fn forward(&mut self, (): (), mut i: BusInterface) {
+        self.calls += 1;
+        let error = i.fire(UNHANDLED, ()).await.unwrap_err();
+        i.fwd_bus_err(error).await
+    }
+
+    async fn count(&mut self, (): (), _i: BusInterface) -> u32 {
+        self.calls
+    }
+}
+
+impl BusStop for Forwarder {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(FORWARD, Self::forward)
+            .handler(COUNT, Self::count)
+    }
+}
+
+#[tokio::test]
+async fn stops_lost_by_fwd_bus_err_are_runtime_invariant() {
+    let mut bus = DABus::new();
+    bus.register(Forwarder::default());
+    let trace = bus.fire(FORWARD, ()).await.unwrap_err();
+    assert!(matches!(bus_error(&trace), BaseFireEventError::NoHandler));
+    // the handler's future was dropped while it had the stop
+    let trace = bus.fire(COUNT, ()).await.unwrap_err();
+    assert!(matches!(
+        bus_error(&trace),
+        BaseFireEventError::RuntimeInvariant(..)
+    ));
+}
+
+event!(KEEP_INTERFACE, (), ());
+
+#[tokio::test]
+async fn fwd_bus_err_panics_with_the_error_once_the_interface_has_expired() {
+    let kept = Arc::new(Mutex::new(None));
+    let mut bus = DABus::new();
+    bus.register_fn(KEEP_INTERFACE, {
+        let kept = kept.clone();
+        move |(), i| {
+            *kept.lock().unwrap() = Some(i);
+            async {}
+        }
+    });
+    bus.fire(KEEP_INTERFACE, ()).await.unwrap();
+    let error = bus.fire(UNHANDLED, ()).await.unwrap_err();
+
+    let interface: BusInterface = kept.lock().unwrap().take().unwrap();
+    assert!(interface.is_expired());
+    let panic = tokio::spawn(async move { interface.fwd_bus_err(error).await })
+        .await
+        .unwrap_err()
+        .into_panic();
+    let message = panic.downcast_ref::<String>().unwrap();
+    assert!(message.starts_with("could not forward an error"));
+    assert!(message.contains(&BaseFireEventError::InterfaceExpired.to_string()));
+    assert!(message.contains("UNHANDLED"));
+}
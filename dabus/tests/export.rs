@@ -0,0 +1,99 @@
+use std::sync::{Arc, Mutex};
+
+use dabus::{
+    bus::{
+        error::{CallEvent, CallTrace},
+        export::{to_otlp_json, OtlpJsonExporter},
+    },
+    event, DABus,
+};
+use serde_json::Value;
+
+event!(OUTER, (), u32);
+event!(MIDDLE, u32, u32);
+event!(INNER, u32, u32);
+
+fn nested_bus() -> DABus {
+    let mut bus = DABus::new();
+    bus.register_fn(OUTER, |(), mut i| async move {
+        i.fire(MIDDLE, 1).await.unwrap() + i.fire(MIDDLE, 2).await.unwrap()
+    });
+    bus.register_fn(MIDDLE, |x, mut i| async move {
+        i.fire(INNER, x).await.unwrap() * 10
+    });
+    bus.register_fn(INNER, |x, _i| async move { x + 1 });
+    bus
+}
+
+fn spans(json: &str) -> Vec<Value> {
+    let request: Value = serde_json::from_str(json).unwrap();
+    let scope_spans = &request["resourceSpans"][0]["scopeSpans"][0];
+    assert_eq!(scope_spans["scope"]["name"], "dabus");
+    scope_spans["spans"].as_array().unwrap().clone()
+}
+
+/// checks that `event` and everything under it was exported with the right ids, returning the number of spans checked
+fn check_span(spans: &[Value], event: &CallEvent, parent: Option<&CallEvent>) -> usize {
+    let context = event.context.unwrap();
+    let span = spans
+        .iter()
+        .find(|span| span["spanId"] == context.span_id.to_string())
+        .unwrap_or_else(|| panic!("no span for {}", event.handler_name));
+    assert_eq!(span["name"], event.handler_name);
+    assert_eq!(span["traceId"], context.trace_id.to_string());
+    match parent {
+        Some(parent) => assert_eq!(
+            span["parentSpanId"],
+            parent.context.unwrap().span_id.to_string()
+        ),
+        None => assert!(span.get("parentSpanId").is_none()),
+    }
+    1 + event
+        .inner
+        .iter()
+        .map(|inner| check_span(spans, inner, Some(event)))
+        .sum::<usize>()
+}
+
+fn check_trace(json: &str, trace: &CallTrace) {
+    let spans = spans(json);
+    let root = trace.root.as_ref().unwrap();
+    // OUTER, two MIDDLEs and an INNER under each
+    assert_eq!(spans.len(), 5);
+    assert_eq!(check_span(&spans, root, None), spans.len());
+
+    let trace_id = root.context.unwrap().trace_id.to_string();
+    assert_eq!(trace_id.len(), 32);
+    assert!(spans.iter().all(|span| span["traceId"] == trace_id));
+}
+
+#[tokio::test]
+async fn nested_calls_share_a_trace() {
+    let mut bus = nested_bus();
+    let result = bus.fire(OUTER, ()).await.unwrap();
+    let trace = result.trace();
+    assert_eq!(result.ret(), 50);
+    check_trace(&to_otlp_json(&trace, "test"), &trace);
+}
+
+#[tokio::test]
+async fn the_exporter_gets_one_request_per_top_level_call() {
+    let exported = Arc::new(Mutex::new(vec![]));
+    let mut bus = nested_bus();
+    bus.set_exporter(OtlpJsonExporter::to_callback({
+        let exported = exported.clone();
+        move |json| exported.lock().unwrap().push(json)
+    }));
+    let first = bus.fire(OUTER, ()).await.unwrap().trace();
+    let second = bus.fire(OUTER, ()).await.unwrap().trace();
+
+    let exported = exported.lock().unwrap();
+    assert_eq!(exported.len(), 2);
+    check_trace(&exported[0], &first);
+    check_trace(&exported[1], &second);
+    // separate top level calls are separate traces
+    assert_ne!(
+        first.root.unwrap().context.unwrap().trace_id,
+        second.root.unwrap().context.unwrap().trace_id
+    );
+}
@@ -0,0 +1,204 @@
+mod common;
+
+use std::{
+    any::type_name,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use common::bus_error;
+use dabus::{
+    bus::{error::BaseFireEventError, export::TraceId},
+    event,
+    event::extract::{Deadline, Resource},
+    BusInterface, BusStop, DABus, EventRegister,
+};
+
+#[derive(Debug)]
+struct Config {
+    greeting: &'static str,
+}
+
+event!(GREET, &'static str, String);
+
+#[derive(Debug)]
+struct Greeter {
+    runs: Arc<AtomicU32>,
+}
+
+impl Greeter {
+    async fn greet(&mut self, name: &'static str, config: Resource<Config>) -> String {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        format!("{}, {name}!", config.greeting)
+    }
+}
+
+impl BusStop for Greeter {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(GREET, Self::greet)
+    }
+}
+
+#[tokio::test]
+async fn missing_resources_fail_without_running_the_handler() {
+    let runs = Arc::new(AtomicU32::new(0));
+    let mut bus = DABus::new();
+    bus.register(Greeter { runs: runs.clone() });
+
+    let trace = bus.fire(GREET, "World").await.unwrap_err();
+    match bus_error(&trace) {
+        BaseFireEventError::MissingResource { resource } => {
+            assert_eq!(resource, type_name::<Config>());
+        }
+        other => panic!("expected a missing resource, found {other:?}"),
+    }
+    assert_eq!(runs.load(Ordering::Relaxed), 0);
+
+    bus.set_resource(Config { greeting: "Hello" });
+    assert_eq!(
+        bus.fire(GREET, "World").await.unwrap().ret(),
+        "Hello, World!"
+    );
+    assert_eq!(runs.load(Ordering::Relaxed), 1);
+}
+
+event!(OUTER, (), (Deadline, Deadline));
+event!(INNER, (), Deadline);
+event!(LATE, (), Result<(), BaseFireEventError>);
+
+#[derive(Debug)]
+struct Caller;
+
+impl Caller {
+    /// returns its own deadline, and the one of the nested call
+    async fn outer(&mut self, deadline: Deadline, mut i: BusInterface) -> (Deadline, Deadline) {
+        (deadline, i.fire(INNER, ()).await.unwrap())
+    }
+
+    /// makes a nested call with a deadline that has already passed
+    async fn late(&mut self, i: BusInterface) -> Result<(), BaseFireEventError> {
+        let mut i = i.with_deadline(SystemTime::now() - Duration::from_secs(1));
+        i.fire(INNER, ())
+            .await
+            .map(|_| ())
+            .map_err(|trace| bus_error(&trace))
+    }
+}
+
+impl BusStop for Caller {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(OUTER, Self::outer).handler(LATE, Self::late)
+    }
+}
+
+#[derive(Debug)]
+struct Callee {
+    runs: Arc<AtomicU32>,
+}
+
+impl Callee {
+    async fn inner(&mut self, deadline: Deadline) -> Deadline {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        deadline
+    }
+}
+
+impl BusStop for Callee {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(INNER, Self::inner)
+    }
+}
+
+fn deadline_bus() -> (DABus, Arc<AtomicU32>) {
+    let runs = Arc::new(AtomicU32::new(0));
+    let mut bus = DABus::new();
+    bus.register(Caller);
+    bus.register(Callee { runs: runs.clone() });
+    (bus, runs)
+}
+
+#[tokio::test]
+async fn nested_calls_share_the_deadline() {
+    let (mut bus, _) = deadline_bus();
+    assert_eq!(
+        bus.fire(OUTER, ()).await.unwrap().ret(),
+        (Deadline(None), Deadline(None))
+    );
+
+    bus.set_timeout(Some(Duration::from_secs(60)));
+    let (outer, inner) = bus.fire(OUTER, ()).await.unwrap().ret();
+    assert!(outer.0.is_some());
+    assert_eq!(outer, inner);
+    assert!(outer.remaining().unwrap() > Duration::from_secs(50));
+}
+
+#[tokio::test]
+async fn calls_past_their_deadline_fail_without_running() {
+    let (mut bus, runs) = deadline_bus();
+    let err = bus.fire(LATE, ()).await.unwrap().ret().unwrap_err();
+    assert!(matches!(err, BaseFireEventError::DeadlineExceeded));
+    assert_eq!(runs.load(Ordering::Relaxed), 0);
+
+    // top level calls have their deadline passed when the timeout is zero
+    bus.set_timeout(Some(Duration::ZERO));
+    let trace = bus.fire(INNER, ()).await.unwrap_err();
+    assert!(matches!(
+        bus_error(&trace),
+        BaseFireEventError::DeadlineExceeded
+    ));
+    assert_eq!(runs.load(Ordering::Relaxed), 0);
+}
+
+event!(TRACE_IDS, (), (TraceId, TraceId));
+event!(TRACE_ID, (), TraceId);
+
+#[derive(Debug)]
+struct Tracer;
+
+impl Tracer {
+    async fn trace_ids(&mut self, trace_id: TraceId, mut i: BusInterface) -> (TraceId, TraceId) {
+        (trace_id, i.fire(TRACE_ID, ()).await.unwrap())
+    }
+}
+
+impl BusStop for Tracer {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(TRACE_IDS, Self::trace_ids)
+    }
+}
+
+#[derive(Debug)]
+struct Traced;
+
+impl Traced {
+    async fn trace_id(&mut self, trace_id: TraceId) -> TraceId {
+        trace_id
+    }
+}
+
+impl BusStop for Traced {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(TRACE_ID, Self::trace_id)
+    }
+}
+
+#[tokio::test]
+async fn nested_calls_share_the_trace_id() {
+    let mut bus = DABus::new();
+    bus.register(Tracer);
+    bus.register(Traced);
+
+    let result = bus.fire(TRACE_IDS, ()).await.unwrap();
+    let trace = result.trace();
+    let (outer, inner) = result.ret();
+    assert_eq!(outer, inner);
+    assert_eq!(trace.root.unwrap().context.unwrap().trace_id, outer);
+
+    // but each top level call gets its own
+    let (next, _) = bus.fire(TRACE_IDS, ()).await.unwrap().ret();
+    assert_ne!(next, outer);
+    assert_ne!(bus.fire(TRACE_ID, ()).await.unwrap().ret(), outer);
+}
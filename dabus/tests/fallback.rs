@@ -0,0 +1,222 @@
+mod common;
+
+use std::{
+    any::type_name,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use common::bus_error;
+use dabus::{
+    bus::{
+        error::{BaseFireEventError, Resolution},
+        fallback::{UnhandledEvent, DEAD_LETTER},
+        metrics::NO_STOP,
+    },
+    event,
+    extras::DynVar,
+    BusInterface, BusStop, DABus, EventRegister,
+};
+
+event!(PORT, (), u16);
+event!(CONNECT, (), u16);
+
+#[tokio::test]
+async fn defaults_are_returned_without_a_handler() {
+    let mut bus = DABus::new();
+    bus.set_default(PORT, 8080);
+    bus.register_fn(CONNECT, |(), mut i| async move {
+        i.fire(PORT, ()).await.unwrap()
+    });
+
+    let result = bus.fire(PORT, ()).await.unwrap();
+    assert!(matches!(
+        result.trace().root.unwrap().resolution,
+        Some(Resolution::Defaulted)
+    ));
+    assert_eq!(result.ret(), 8080);
+
+    let result = bus.fire(CONNECT, ()).await.unwrap();
+    let root = result.trace().root.unwrap();
+    assert!(matches!(root.resolution, Some(Resolution::Success)));
+    assert!(matches!(
+        root.inner[0].resolution,
+        Some(Resolution::Defaulted)
+    ));
+    assert_eq!(result.ret(), 8080);
+
+    // a handler takes precedence over the default
+    bus.register_fn(PORT, |(), _i| async { 443 });
+    assert_eq!(bus.fire(PORT, ()).await.unwrap().ret(), 443);
+}
+
+event!(GREET, String, String);
+
+/// the events passed to the dead letter handler, as `(name, args type, return type, args)`
+type Unhandled = Arc<Mutex<Vec<(&'static str, &'static str, &'static str, Option<String>)>>>;
+
+/// registers a dead letter handler recording the events it gets, and returning `reply` for them
+fn register_dead_letters(bus: &mut DABus, reply: fn() -> Option<DynVar>) -> Unhandled {
+    let unhandled = Unhandled::default();
+    bus.register_fn(DEAD_LETTER, {
+        let unhandled = unhandled.clone();
+        move |event: UnhandledEvent, _i| {
+            unhandled.lock().unwrap().push((
+                event.name,
+                event.args_type,
+                event.return_type,
+                event.args.try_to::<String>().ok(),
+            ));
+            async move { reply() }
+        }
+    });
+    unhandled
+}
+
+#[tokio::test]
+async fn unhandled_events_are_passed_to_the_dead_letter_handler() {
+    let mut bus = DABus::new();
+    let unhandled = register_dead_letters(&mut bus, || Some(DynVar::new(String::from("hi"))));
+    assert_eq!(
+        bus.fire(GREET, String::from("world")).await.unwrap().ret(),
+        "hi"
+    );
+    assert_eq!(
+        *unhandled.lock().unwrap(),
+        [(
+            "GREET",
+            type_name::<String>(),
+            type_name::<String>(),
+            Some(String::from("world"))
+        )]
+    );
+}
+
+#[tokio::test]
+async fn dead_letter_replies_of_the_wrong_type_are_type_mismatch() {
+    let mut bus = DABus::new();
+    register_dead_letters(&mut bus, || Some(DynVar::new(7u32)));
+    let trace = bus.fire(GREET, String::from("world")).await.unwrap_err();
+    match bus_error(&trace) {
+        BaseFireEventError::TypeMismatch { expected, found } => {
+            assert_eq!(expected, type_name::<String>());
+            assert_eq!(found, type_name::<u32>());
+        }
+        other => panic!("expected a type mismatch, found {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn unanswered_dead_letters_are_no_handler() {
+    let mut bus = DABus::new();
+    let unhandled = register_dead_letters(&mut bus, || None);
+    let trace = bus.fire(GREET, String::from("world")).await.unwrap_err();
+    assert!(matches!(bus_error(&trace), BaseFireEventError::NoHandler));
+    assert_eq!(unhandled.lock().unwrap().len(), 1);
+}
+
+event!(RUN, (), ());
+event!(LOG, &'static str, ());
+
+#[derive(Debug)]
+struct Job {
+    runs: Arc<AtomicU32>,
+}
+
+impl Job {
+    async fn run(&mut self, (): (), mut i: BusInterface) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        // fails unless something handles `LOG`
+        let _ = i.fire(LOG, "ran").await;
+    }
+}
+
+impl BusStop for Job {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(RUN, Self::run).requires(LOG)
+    }
+}
+
+#[tokio::test]
+async fn strict_mode_fails_each_top_level_call_once() {
+    let runs = Arc::new(AtomicU32::new(0));
+    let mut bus = DABus::new();
+    bus.register(Job { runs: runs.clone() });
+    let missing = bus.verify().unwrap_err().missing;
+    assert_eq!(missing.len(), 1);
+    assert_eq!(missing[0].event, "LOG");
+
+    // without strict mode, the handler runs and its call to `LOG` fails
+    bus.fire(RUN, ()).await.unwrap();
+    assert_eq!(runs.load(Ordering::Relaxed), 1);
+
+    bus.set_strict(true);
+    for _ in 0..2 {
+        let trace = bus.fire(RUN, ()).await.unwrap_err();
+        assert!(trace.root.as_ref().unwrap().inner.is_empty());
+        match bus_error(&trace) {
+            BaseFireEventError::MissingHandlers(missing) => assert_eq!(missing.missing.len(), 1),
+            other => panic!("expected missing handlers, found {other:?}"),
+        }
+    }
+    // the handler never ran, and each call failed once
+    assert_eq!(runs.load(Ordering::Relaxed), 1);
+    let metrics = bus.metrics();
+    let failed = metrics.get("RUN", NO_STOP).unwrap();
+    assert_eq!(failed.calls, 2);
+    assert_eq!(failed.errors.get("bus_error"), Some(&2));
+
+    // a default value counts as handling the event
+    bus.set_default(LOG, ());
+    assert!(bus.verify().is_ok());
+    bus.fire(RUN, ()).await.unwrap();
+    assert_eq!(runs.load(Ordering::Relaxed), 2);
+}
+
+event!(NOTIFY, u32, u32);
+event!(FORWARD, u32, Option<u32>);
+
+#[tokio::test]
+async fn try_fire_uses_defaults() {
+    let mut bus = DABus::new();
+    bus.set_default(PORT, 8080);
+    let result = bus.try_fire(PORT, ()).await.unwrap().unwrap();
+    assert!(matches!(
+        result.trace().root.unwrap().resolution,
+        Some(Resolution::Defaulted)
+    ));
+    assert_eq!(result.ret(), 8080);
+
+    bus.register_fn(CONNECT, |(), mut i| async move {
+        i.try_fire(PORT, ()).await.unwrap().unwrap()
+    });
+    assert_eq!(bus.fire(CONNECT, ()).await.unwrap().ret(), 8080);
+}
+
+#[tokio::test]
+async fn try_fire_passes_unhandled_events_to_the_dead_letter_handler() {
+    let mut bus = DABus::new();
+    let unhandled = register_dead_letters(&mut bus, || Some(DynVar::new(String::from("hi"))));
+    assert_eq!(
+        bus.try_fire(GREET, String::from("world"))
+            .await
+            .unwrap()
+            .unwrap()
+            .ret(),
+        "hi"
+    );
+    bus.register_fn(FORWARD, |x, mut i| async move {
+        i.try_fire(NOTIFY, x).await.ok().flatten()
+    });
+    // the dead letter handler does not answer `NOTIFY`, so the nested call fails
+    assert_eq!(bus.fire(FORWARD, 1).await.unwrap().ret(), None);
+    let names = unhandled
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, ..)| *name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["GREET", "NOTIFY"]);
+}
@@ -0,0 +1,103 @@
+use dabus::{
+    bus::error::{HandlerError, Resolution},
+    event, BusInterface, BusStop, DABus, EventRegister,
+};
+
+#[derive(Debug, thiserror::Error)]
+#[error("the disk is full")]
+struct DiskFull;
+
+#[derive(Debug, thiserror::Error)]
+#[error("could not save {0}")]
+struct SaveFailed(&'static str, #[source] DiskFull);
+
+event!(SAVE, &'static str, Result<(), SaveFailed>);
+event!(SAVE_ALL, Vec<&'static str>, usize);
+
+#[derive(Debug)]
+struct Storage;
+
+impl Storage {
+    async fn save(&mut self, name: &'static str, _i: BusInterface) -> Result<(), SaveFailed> {
+        if name.is_empty() {
+            Ok(())
+        } else {
+            Err(SaveFailed(name, DiskFull))
+        }
+    }
+}
+
+impl BusStop for Storage {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.fallible_handler(SAVE, Self::save)
+    }
+}
+
+fn handler_error(resolution: Option<Resolution>) -> HandlerError {
+    match resolution {
+        Some(Resolution::HandlerError(err)) => err,
+        other => panic!("expected a handler error, found {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn errors_are_recorded_as_handler_errors() {
+    let mut bus = DABus::new();
+    bus.register(Storage);
+    let result = bus.fire(SAVE, "notes").await.unwrap();
+    let trace = result.trace();
+    // the error is still returned to the caller
+    assert!(matches!(result.ret(), Err(SaveFailed("notes", DiskFull))));
+
+    let source = trace.source().expect("the failed call is the source");
+    assert_eq!(source.handler_name, "SAVE");
+    let err = handler_error(source.resolution);
+    assert!(err.type_name.ends_with("SaveFailed"));
+    assert_eq!(err.message, "could not save notes");
+    assert_eq!(err.sources, ["the disk is full"]);
+    assert!(trace.to_string().contains("could not save notes"));
+}
+
+#[tokio::test]
+async fn ok_is_recorded_as_success() {
+    let mut bus = DABus::new();
+    bus.register(Storage);
+    let result = bus.fire(SAVE, "").await.unwrap();
+    let trace = result.trace();
+    assert!(result.ret().is_ok());
+    assert!(matches!(
+        trace.root.as_ref().unwrap().resolution,
+        Some(Resolution::Success)
+    ));
+    assert!(trace.source().is_none());
+}
+
+#[tokio::test]
+async fn nested_errors_are_recorded_without_failing_the_caller() {
+    let mut bus = DABus::new();
+    bus.register(Storage);
+    // counts the failed saves
+    bus.register_fn(SAVE_ALL, |names, mut i| async move {
+        i.fire_all(SAVE, names)
+            .await
+            .into_iter()
+            .filter(|result| matches!(result, Ok(Err(..))))
+            .count()
+    });
+    let result = bus.fire(SAVE_ALL, vec!["a", "", "b"]).await.unwrap();
+    let trace = result.trace();
+    assert_eq!(result.ret(), 2);
+    // the caller handled the errors, so the call as a whole did not fail
+    assert!(trace.source().is_none());
+
+    let root = trace.root.unwrap();
+    let errors = root
+        .inner
+        .into_iter()
+        .filter_map(|event| match event.resolution {
+            Some(Resolution::HandlerError(err)) => Some(err.message),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(errors, ["could not save a", "could not save b"]);
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use dabus::{event, DABus, FnStop};
+
+event!(ADD, u32, ());
+event!(TOTAL, (), u32);
+event!(STATELESS, u32, u32);
+
+#[tokio::test]
+async fn handlers_share_the_stop_state() {
+    let mut bus = DABus::new();
+    let handle = bus.register_fn_stop(
+        FnStop::with_state("total", 0u32)
+            .state_handler(ADD, |total, value, _i| {
+                Box::pin(async move {
+                    let before = *total;
+                    // the state stays borrowed across the await
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    *total = before + value;
+                })
+            })
+            .state_handler(TOTAL, |total, (), _i| Box::pin(async move { *total }))
+            .handler(STATELESS, |value, _i| async move { value * 2 }),
+    );
+
+    for value in [1, 2, 3] {
+        bus.fire(ADD, value).await.unwrap();
+    }
+    assert_eq!(bus.fire(TOTAL, ()).await.unwrap().ret(), 6);
+    assert_eq!(bus.fire(STATELESS, 4).await.unwrap().ret(), 8);
+    assert!(bus.remove(handle));
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::bus_error;
+use dabus::{
+    bus::error::{BaseFireEventError, CallTrace, Resolution},
+    event, BusInterface, BusStop, DABus, EventRegister,
+};
+
+event!(OUTER, (), u32);
+event!(MIDDLE, (), u32);
+event!(INNER, (), u32);
+
+#[derive(Debug)]
+struct Outer;
+
+impl Outer {
+    async fn outer(&mut self, (): (), mut i: BusInterface) -> Result<u32, CallTrace> {
+        Ok(i.fire(MIDDLE, ()).await? + 1)
+    }
+}
+
+impl BusStop for Outer {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.forwarding_handler(OUTER, Self::outer)
+    }
+}
+
+#[derive(Debug)]
+struct Middle;
+
+impl Middle {
+    async fn middle(&mut self, (): (), mut i: BusInterface) -> Result<u32, CallTrace> {
+        Ok(i.fire(INNER, ()).await? * 2)
+    }
+}
+
+impl BusStop for Middle {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.forwarding_handler(MIDDLE, Self::middle)
+    }
+}
+
+fn forwarding_bus() -> DABus {
+    let mut bus = DABus::new();
+    bus.register(Outer);
+    bus.register(Middle);
+    bus
+}
+
+#[tokio::test]
+async fn failed_nested_calls_are_forwarded_with_their_trace() {
+    let mut bus = forwarding_bus();
+    let trace = bus.fire(OUTER, ()).await.unwrap_err();
+
+    let outer = trace.root.as_ref().unwrap();
+    assert_eq!(outer.handler_name, "OUTER");
+    assert!(matches!(
+        outer.resolution,
+        Some(Resolution::NestedCallError)
+    ));
+    let middle = &outer.inner[0];
+    assert_eq!(middle.handler_name, "MIDDLE");
+    assert!(matches!(
+        middle.resolution,
+        Some(Resolution::NestedCallError)
+    ));
+    let inner = &middle.inner[0];
+    assert_eq!(inner.handler_name, "INNER");
+
+    assert_eq!(trace.source().unwrap().handler_name, "INNER");
+    assert!(matches!(bus_error(&trace), BaseFireEventError::NoHandler));
+}
+
+#[tokio::test]
+async fn forwarding_stops_are_kept() {
+    let mut bus = forwarding_bus();
+    assert!(bus.fire(OUTER, ()).await.is_err());
+    // unlike `fwd_bus_err`, returning the error gives the stops back
+    bus.register_fn(INNER, |(), _i| async { 20 });
+    let result = bus.fire(OUTER, ()).await.unwrap();
+    assert!(matches!(
+        result.trace().root.unwrap().resolution,
+        Some(Resolution::Success)
+    ));
+    assert_eq!(result.ret(), 41);
+}
@@ -0,0 +1,37 @@
+use std::any::type_name;
+
+use dabus::{event, DABus, Event};
+
+event!(LOAD<T>, (), Option<T>);
+event!(PAIR<A, B>, (A, B), ());
+
+#[test]
+fn generic_events_are_named_after_their_parameters() {
+    assert_eq!(LOAD::<u32>::DEF.name(), "LOAD<u32>");
+    assert_eq!(
+        LOAD::<String>::DEF.name(),
+        format!("LOAD<{}>", type_name::<String>())
+    );
+    assert_eq!(
+        PAIR::<u8, Vec<u8>>::DEF.name(),
+        format!("PAIR<u8, {}>", type_name::<Vec<u8>>())
+    );
+    // names are built once, and then reused
+    assert!(std::ptr::eq(
+        LOAD::<u32>::DEF.name(),
+        LOAD::<u32>::DEF.name()
+    ));
+}
+
+#[tokio::test]
+async fn each_instantiation_is_a_separate_event() {
+    let mut bus = DABus::new();
+    bus.register_fn(LOAD::<u32>::DEF, |(), _i| async { Some(5) });
+    assert_eq!(bus.fire(LOAD::<u32>::DEF, ()).await.unwrap().ret(), Some(5));
+    let trace = bus.fire(LOAD::<u64>::DEF, ()).await.unwrap_err();
+    assert_eq!(trace.root.unwrap().handler_name, "LOAD<u64>");
+
+    let metrics = bus.metrics();
+    let events = metrics.iter().map(|(key, _)| key.event).collect::<Vec<_>>();
+    assert_eq!(events, ["LOAD<u32>", "LOAD<u64>"]);
+}
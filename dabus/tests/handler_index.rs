@@ -0,0 +1,48 @@
+use dabus::{event, DABus};
+
+event!(NOTIFY, u32, u32);
+event!(FORWARD, u32, Option<u32>);
+
+#[tokio::test]
+async fn try_fire_without_a_handler_is_none() {
+    let mut bus = DABus::new();
+    assert!(!bus.has_handler(NOTIFY));
+    assert!(bus.try_fire(NOTIFY, 1).await.unwrap().is_none());
+    assert_eq!(bus.metrics().iter().count(), 0);
+
+    bus.register_fn(NOTIFY, |x, _i| async move { x + 1 });
+    assert!(bus.has_handler(NOTIFY));
+    assert_eq!(bus.try_fire(NOTIFY, 1).await.unwrap().unwrap().ret(), 2);
+}
+
+#[tokio::test]
+async fn nested_try_fire_without_a_handler_is_none() {
+    let mut bus = DABus::new();
+    bus.register_fn(FORWARD, |x, mut i| async move {
+        i.try_fire(NOTIFY, x).await.unwrap()
+    });
+    let result = bus.fire(FORWARD, 1).await.unwrap();
+    assert!(result.trace().root.unwrap().inner.is_empty());
+    assert_eq!(result.ret(), None);
+
+    bus.register_fn(NOTIFY, |x, _i| async move { x + 1 });
+    assert_eq!(bus.fire(FORWARD, 1).await.unwrap().ret(), Some(2));
+}
+
+#[test]
+#[should_panic(expected = "only one stop can handle each event")]
+fn registering_a_second_handler_panics() {
+    let mut bus = DABus::new();
+    bus.register_fn(NOTIFY, |x, _i| async move { x });
+    bus.register_fn(NOTIFY, |x, _i| async move { x + 1 });
+}
+
+#[tokio::test]
+async fn removed_handlers_can_be_replaced() {
+    let mut bus = DABus::new();
+    let handle = bus.register_fn(NOTIFY, |x, _i| async move { x });
+    assert!(bus.remove(handle));
+    assert!(!bus.has_handler(NOTIFY));
+    bus.register_fn(NOTIFY, |x, _i| async move { x + 1 });
+    assert_eq!(bus.fire(NOTIFY, 1).await.unwrap().ret(), 2);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::bus_error;
+use dabus::{bus::error::BaseFireEventError, event, BusInterface, DABus};
+
+event!(KEEP_INTERFACE, (), bool);
+event!(ECHO, u32, u32);
+
+/// a bus that hands out a clone of the interface given to the `KEEP_INTERFACE` handler
+fn keeping_bus() -> (DABus, Arc<Mutex<Option<BusInterface>>>) {
+    let kept = Arc::new(Mutex::new(None));
+    let mut bus = DABus::new();
+    bus.register_fn(KEEP_INTERFACE, {
+        let kept = kept.clone();
+        move |(), i: BusInterface| {
+            let expired = i.is_expired();
+            *kept.lock().unwrap() = Some(i.clone());
+            async move { expired }
+        }
+    });
+    bus.register_fn(ECHO, |x, _i| async move { x });
+    (bus, kept)
+}
+
+#[tokio::test]
+async fn interfaces_used_after_their_handler_returns_are_expired() {
+    let (mut bus, kept) = keeping_bus();
+    assert!(!bus.fire(KEEP_INTERFACE, ()).await.unwrap().ret());
+
+    let mut interface = kept.lock().unwrap().take().unwrap();
+    assert!(interface.is_expired());
+    let trace = interface.fire(ECHO, 1).await.unwrap_err();
+    assert!(matches!(
+        bus_error(&trace),
+        BaseFireEventError::InterfaceExpired
+    ));
+    assert!(matches!(
+        bus_error(&interface.try_fire(ECHO, 1).await.unwrap_err()),
+        BaseFireEventError::InterfaceExpired
+    ));
+
+    // the bus itself is unaffected
+    assert_eq!(bus.fire(ECHO, 2).await.unwrap().ret(), 2);
+}
+
+event!(SPAWN_CALL, (), ());
+
+#[tokio::test]
+async fn interfaces_moved_into_tasks_expire_with_their_handler() {
+    let (result, response) = flume::bounded(1);
+    let (release, released) = flume::bounded::<()>(1);
+    let mut bus = DABus::new();
+    bus.register_fn(ECHO, |x, _i| async move { x });
+    bus.register_fn(SPAWN_CALL, move |(), mut i| {
+        let result = result.clone();
+        let released = released.clone();
+        tokio::spawn(async move {
+            // only fires once the handler has returned
+            let _ = released.recv_async().await;
+            let _ = result.send(i.fire(ECHO, 1).await);
+        });
+        async {}
+    });
+    bus.fire(SPAWN_CALL, ()).await.unwrap();
+    release.send(()).unwrap();
+
+    let trace = response.recv_async().await.unwrap().unwrap_err();
+    assert!(matches!(
+        bus_error(&trace),
+        BaseFireEventError::InterfaceExpired
+    ));
+}
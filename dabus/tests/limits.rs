@@ -0,0 +1,65 @@
+mod common;
+
+use common::{bus_error, register_fan_out, Outcome, FAN_OUT};
+use dabus::{bus::error::BaseFireEventError, event, DABus};
+
+event!(OUTER, (), Outcome);
+event!(MIDDLE, (), Outcome);
+event!(INNER, (), Outcome);
+
+fn nested_bus() -> DABus {
+    let mut bus = DABus::new();
+    bus.register_fn(OUTER, |(), mut i| async move {
+        i.fire(MIDDLE, ())
+            .await
+            .map_err(|trace| bus_error(&trace))?
+    });
+    bus.register_fn(MIDDLE, |(), mut i| async move {
+        i.fire(INNER, ()).await.map_err(|trace| bus_error(&trace))?
+    });
+    bus.register_fn(INNER, |(), _i| async { Ok(()) });
+    bus
+}
+
+#[tokio::test]
+async fn calls_past_the_max_depth_fail() {
+    let mut bus = nested_bus();
+    bus.set_max_depth(Some(2));
+    assert!(bus.fire(OUTER, ()).await.unwrap().ret().is_ok());
+
+    bus.set_max_depth(Some(1));
+    let result = bus.fire(OUTER, ()).await.unwrap().ret();
+    assert!(matches!(
+        result,
+        Err(BaseFireEventError::DepthExceeded { limit: 1 })
+    ));
+
+    bus.set_max_depth(None);
+    assert!(bus.fire(OUTER, ()).await.unwrap().ret().is_ok());
+}
+
+event!(LEAF, (), ());
+
+#[tokio::test]
+async fn calls_past_the_budget_fail() {
+    let mut bus = DABus::new();
+    register_fan_out(&mut bus, LEAF);
+    bus.register_fn(LEAF, |(), _i| async {});
+    bus.set_call_budget(Some(2));
+
+    let results = bus.fire(FAN_OUT, 3).await.unwrap().ret();
+    assert!(results[..2].iter().all(Result::is_ok));
+    assert!(matches!(
+        results[2],
+        Err(BaseFireEventError::BudgetExceeded { limit: 2 })
+    ));
+
+    // the budget is for each top level call
+    assert!(bus
+        .fire(FAN_OUT, 2)
+        .await
+        .unwrap()
+        .ret()
+        .iter()
+        .all(Result::is_ok));
+}
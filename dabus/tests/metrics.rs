@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use dabus::{bus::metrics::NO_STOP, event, DABus, FnStop};
+
+#[derive(Debug, thiserror::Error)]
+#[error("division by zero")]
+struct DivByZero;
+
+event!(DIVIDE, (u32, u32), Result<u32, DivByZero>);
+event!(MISSING, (), ());
+
+fn math_bus() -> DABus {
+    let mut bus = DABus::new();
+    bus.register_fn_stop(
+        FnStop::new("math").fallible_handler(DIVIDE, |(a, b), _i| async move {
+            a.checked_div(b).ok_or(DivByZero)
+        }),
+    );
+    bus
+}
+
+#[tokio::test]
+async fn calls_and_errors_are_counted() {
+    let mut bus = math_bus();
+    assert!(bus.fire(DIVIDE, (6, 3)).await.unwrap().ret().is_ok());
+    assert!(bus.fire(DIVIDE, (6, 2)).await.unwrap().ret().is_ok());
+    assert!(bus.fire(DIVIDE, (6, 0)).await.unwrap().ret().is_err());
+    assert!(bus.fire(MISSING, ()).await.is_err());
+
+    let metrics = bus.metrics();
+    let event = String::from("DIVIDE");
+    let divide = metrics.get(&event, "math").unwrap();
+    assert_eq!(divide.calls, 3);
+    assert_eq!(divide.in_flight, 0);
+    assert_eq!(divide.latency.count, 3);
+    assert_eq!(divide.errors.len(), 1);
+    assert_eq!(divide.errors.get("handler_error"), Some(&1));
+
+    let missing = metrics.get("MISSING", NO_STOP).unwrap();
+    assert_eq!(missing.calls, 1);
+    assert_eq!(missing.errors.get("bus_error"), Some(&1));
+
+    let keys = metrics
+        .iter()
+        .map(|(key, _)| (key.event, key.stop))
+        .collect::<Vec<_>>();
+    assert_eq!(keys, [("DIVIDE", "math"), ("MISSING", NO_STOP)]);
+}
+
+#[tokio::test]
+async fn metrics_are_rendered_for_prometheus() {
+    let mut bus = math_bus();
+    bus.fire(DIVIDE, (6, 3)).await.unwrap();
+    bus.fire(DIVIDE, (6, 0)).await.unwrap();
+
+    let text = bus.metrics().to_prometheus();
+    let lines = text.lines().collect::<Vec<_>>();
+    for line in [
+        "# TYPE dabus_calls_total counter",
+        r#"dabus_calls_total{event="DIVIDE",stop="math"} 2"#,
+        "# TYPE dabus_call_errors_total counter",
+        r#"dabus_call_errors_total{event="DIVIDE",stop="math",resolution="handler_error"} 1"#,
+        "# TYPE dabus_calls_in_flight gauge",
+        r#"dabus_calls_in_flight{event="DIVIDE",stop="math"} 0"#,
+        "# TYPE dabus_call_duration_seconds histogram",
+        r#"dabus_call_duration_seconds_bucket{event="DIVIDE",stop="math",le="+Inf"} 2"#,
+        r#"dabus_call_duration_seconds_count{event="DIVIDE",stop="math"} 2"#,
+    ] {
+        assert!(lines.contains(&line), "missing {line:?} in:\n{text}");
+    }
+}
+
+event!(OUTER, (), ());
+event!(STUCK, (), ());
+
+#[tokio::test]
+async fn abandoned_calls_are_not_left_in_flight() {
+    let mut bus = DABus::new();
+    bus.register_fn_stop(FnStop::new("outer").handler(OUTER, |(), mut i| async move {
+        i.fire(STUCK, ()).await.unwrap();
+    }));
+    bus.register_fn_stop(
+        FnStop::new("stuck").handler(STUCK, |(), _i| futures::future::pending::<()>()),
+    );
+    let abandoned = tokio::time::timeout(Duration::from_millis(10), bus.fire(OUTER, ())).await;
+    assert!(abandoned.is_err());
+
+    let metrics = bus.metrics();
+    for (event, stop) in [("OUTER", "outer"), ("STUCK", "stuck")] {
+        let call = metrics.get(event, stop).unwrap();
+        assert_eq!(call.calls, 1);
+        assert_eq!(call.in_flight, 0);
+        // the calls never finished, so they have no latency
+        assert_eq!(call.latency.count, 0);
+    }
+    assert!(bus
+        .metrics()
+        .to_prometheus()
+        .lines()
+        .any(|line| line == r#"dabus_calls_in_flight{event="STUCK",stop="stuck"} 0"#));
+}
@@ -0,0 +1,54 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use dabus::{
+    bus::policy::{Backoff, RetryPolicy},
+    event, DABus, FnStop,
+};
+
+event!(FLAKY, (), Result<u32, std::fmt::Error>);
+event!(LOOKUP, (), u32);
+
+#[tokio::test]
+async fn failed_attempts_are_kept_apart_from_nested_calls() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let mut bus = DABus::new();
+    bus.register_fn_stop(FnStop::new("flaky").fallible_handler(FLAKY, {
+        let attempts = attempts.clone();
+        move |(), mut i| {
+            let attempt = attempts.fetch_add(1, Ordering::Relaxed);
+            async move {
+                let value = i.fire(LOOKUP, ()).await.unwrap();
+                if attempt < 2 {
+                    Err(std::fmt::Error)
+                } else {
+                    Ok(value)
+                }
+            }
+        }
+    }));
+    bus.register_fn(LOOKUP, |(), _i| async { 7 });
+    bus.set_retry(
+        FLAKY,
+        RetryPolicy::new(3).with_backoff(Backoff::Fixed(Duration::from_millis(10))),
+    );
+
+    let started = Instant::now();
+    let result = bus.fire(FLAKY, ()).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(20));
+    let root = result.trace().root.unwrap();
+    assert_eq!(result.ret().unwrap(), 7);
+    assert_eq!(root.attempt, 2);
+    assert_eq!(root.failed_attempts.len(), 2);
+    assert_eq!(root.inner.len(), 1);
+    for (attempt, failed) in root.failed_attempts.iter().enumerate() {
+        assert_eq!(failed.attempt as usize, attempt);
+        assert!(failed.resolution.as_ref().unwrap().is_error());
+        assert_eq!(failed.inner.len(), 1);
+    }
+}
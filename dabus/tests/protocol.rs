@@ -0,0 +1,152 @@
+mod common;
+
+use std::any::type_name;
+
+use common::bus_error;
+use dabus::{
+    bus::error::BaseFireEventError, event, event::protocol::Protocol, protocol, BusInterface,
+    BusStop, DABus, EventRegister,
+};
+
+protocol! {
+    /// a counter
+    pub struct Counter;
+
+    pub fn add(COUNTER_ADD): u32 => u32;
+    pub fn get(COUNTER_GET): () => u32;
+    /// never compiled, so neither the event nor the method exist
+    #[cfg(any())]
+    pub fn reset(COUNTER_RESET): () => ();
+    #[cfg(all())]
+    pub fn double(COUNTER_DOUBLE): () => u32;
+}
+
+#[derive(Debug, Default)]
+struct Count(u32);
+
+impl Count {
+    async fn add(&mut self, n: u32, _i: BusInterface) -> u32 {
+        self.0 += n;
+        self.0
+    }
+
+    async fn get(&mut self, (): (), _i: BusInterface) -> u32 {
+        self.0
+    }
+
+    async fn double(&mut self, (): (), _i: BusInterface) -> u32 {
+        self.0 *= 2;
+        self.0
+    }
+}
+
+impl BusStop for Count {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(COUNTER_ADD, Self::add)
+            .handler(COUNTER_GET, Self::get)
+            .handler(COUNTER_DOUBLE, Self::double)
+    }
+}
+
+#[test]
+fn cfg_removes_events_from_the_protocol() {
+    let events = Counter::<()>::events()
+        .into_iter()
+        .map(|(_, name)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(events, ["COUNTER_ADD", "COUNTER_GET", "COUNTER_DOUBLE"]);
+    assert_eq!(Counter::<()>::NAME, "Counter");
+}
+
+#[tokio::test]
+async fn clients_fire_the_protocol_events() {
+    let mut bus = DABus::new();
+    let missing = bus.verify_protocol::<Counter>().unwrap_err().missing;
+    assert_eq!(missing.len(), 3);
+    assert!(missing.iter().all(|event| event.required_by == "Counter"));
+
+    bus.register(Count::default());
+    bus.verify_protocol::<Counter>().unwrap();
+    let mut counter = Counter(&mut bus);
+    assert_eq!(counter.add(3).await.unwrap(), 3);
+    assert_eq!(counter.double(()).await.unwrap(), 6);
+    assert_eq!(counter.get(()).await.unwrap(), 6);
+}
+
+event!(REPORT, (), u32);
+
+#[derive(Debug)]
+struct Reporter;
+
+impl Reporter {
+    async fn report(&mut self, (): (), i: BusInterface) -> u32 {
+        let mut counter = Counter(i);
+        counter.add(1).await.unwrap();
+        counter.get(()).await.unwrap()
+    }
+}
+
+impl BusStop for Reporter {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(REPORT, Self::report)
+            .requires_protocol::<Counter>()
+    }
+}
+
+#[tokio::test]
+async fn strict_mode_checks_required_protocols() {
+    let mut bus = DABus::new();
+    bus.register(Reporter);
+    bus.set_strict(true);
+
+    let trace = bus.fire(REPORT, ()).await.unwrap_err();
+    match bus_error(&trace) {
+        BaseFireEventError::MissingHandlers(missing) => {
+            let events = missing
+                .missing
+                .iter()
+                .map(|event| event.event)
+                .collect::<Vec<_>>();
+            assert_eq!(events, ["COUNTER_ADD", "COUNTER_GET", "COUNTER_DOUBLE"]);
+            assert!(missing
+                .missing
+                .iter()
+                .all(|event| event.required_by == type_name::<Reporter>()));
+        }
+        other => panic!("expected missing handlers, found {other:?}"),
+    }
+
+    bus.register(Count::default());
+    bus.verify().unwrap();
+    assert_eq!(bus.fire(REPORT, ()).await.unwrap().ret(), 1);
+}
+
+mod shapes {
+    dabus::events! {
+        pub trait Shapes;
+
+        AREA: f64 => f64;
+        pub(crate) PERIMETER: f64 => f64;
+    }
+
+    dabus::protocol! {
+        pub struct Geometry;
+
+        fn area(GEOMETRY_AREA): f64 => f64;
+        pub(crate) fn perimeter(GEOMETRY_PERIMETER): f64 => f64;
+    }
+}
+
+#[tokio::test]
+async fn items_without_a_visibility_are_pub() {
+    let mut bus = DABus::new();
+    bus.register_fn(shapes::GEOMETRY_AREA, |side, _i| async move { side * side });
+    bus.register_fn(
+        shapes::GEOMETRY_PERIMETER,
+        |side, _i| async move { side * 4.0 },
+    );
+    let mut geometry = shapes::Geometry(&mut bus);
+    assert!((geometry.area(2.0).await.unwrap() - 4.0).abs() < f64::EPSILON);
+    assert!((geometry.perimeter(2.0).await.unwrap() - 8.0).abs() < f64::EPSILON);
+    let _ = (shapes::AREA, shapes::PERIMETER);
+}
@@ -0,0 +1,54 @@
+use std::time::{Duration, Instant};
+
+mod common;
+
+use common::{register_fan_out, FAN_OUT};
+use dabus::{
+    bus::{
+        error::BaseFireEventError,
+        policy::{OverLimit, RateLimit},
+    },
+    event, DABus,
+};
+
+event!(LIMITED, (), ());
+
+fn limited_bus(over_limit: OverLimit) -> DABus {
+    let mut bus = DABus::new();
+    register_fan_out(&mut bus, LIMITED);
+    bus.register_fn(LIMITED, |(), _i| async {});
+    bus.set_rate_limit(
+        LIMITED,
+        RateLimit::new(over_limit).with_rate(1, Duration::from_millis(20)),
+    );
+    bus
+}
+
+#[tokio::test]
+async fn rate_limited_calls_are_released() {
+    let mut bus = limited_bus(OverLimit::Wait);
+    let started = Instant::now();
+    let results = bus.fire(FAN_OUT, 3).await.unwrap().ret();
+    assert!(results.iter().all(Result::is_ok));
+    assert!(started.elapsed() >= Duration::from_millis(40));
+}
+
+#[tokio::test]
+async fn rate_limited_calls_are_released_when_the_sleeper_wakes_early() {
+    let mut bus = limited_bus(OverLimit::Wait);
+    bus.set_sleeper(|duration: Duration| Box::pin(tokio::time::sleep(duration / 4)) as _);
+    let results = tokio::time::timeout(Duration::from_secs(5), bus.fire(FAN_OUT, 3))
+        .await
+        .expect("the queued calls were never released")
+        .unwrap()
+        .ret();
+    assert!(results.iter().all(Result::is_ok));
+}
+
+#[tokio::test]
+async fn calls_over_the_limit_fail() {
+    let mut bus = limited_bus(OverLimit::Fail);
+    let results = bus.fire(FAN_OUT, 2).await.unwrap().ret();
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(BaseFireEventError::RateLimited)));
+}
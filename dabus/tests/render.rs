@@ -0,0 +1,117 @@
+use dabus::{bus::error::CallTrace, event, DABus, FnStop};
+
+event!(OUTER, (), u32);
+event!(DOUBLE, u32, u32);
+event!(MIDDLE, (), u32);
+event!(MISSING, String, ());
+
+/// `OUTER` calls `DOUBLE`, which succeeds, and then `MIDDLE`, which fails calling `MISSING`
+async fn failing_trace() -> CallTrace {
+    let mut bus = DABus::new();
+    bus.register_fn_stop(FnStop::new("outer").handler(OUTER, |(), mut i| async move {
+        let doubled = i.fire(DOUBLE, 3).await.unwrap();
+        match i.fire(MIDDLE, ()).await {
+            Ok(x) => x + doubled,
+            Err(trace) => i.fwd_bus_err(trace).await,
+        }
+    }));
+    bus.register_fn_stop(FnStop::new("double").handler(DOUBLE, |x, _i| async move { x * 2 }));
+    bus.register_fn_stop(
+        FnStop::new("middle").handler(MIDDLE, |(), mut i| async move {
+            if let Err(trace) = i.fire(MISSING, String::from("hello")).await {
+                i.fwd_bus_err(trace).await;
+            }
+            0
+        }),
+    );
+    bus.fire(OUTER, ()).await.unwrap_err()
+}
+
+#[tokio::test]
+async fn source_is_the_innermost_failed_call() {
+    let trace = failing_trace().await;
+    assert_eq!(trace.source().unwrap().handler_name, "MISSING");
+}
+
+#[tokio::test]
+async fn the_root_is_the_source_when_it_fails_itself() {
+    let mut bus = DABus::new();
+    let trace = bus.fire(MISSING, String::new()).await.unwrap_err();
+    let source = trace.source().unwrap();
+    assert_eq!(source.handler_name, "MISSING");
+    assert!(source.inner.is_empty());
+}
+
+#[cfg(not(any(feature = "backtrace_track_values", feature = "backtrace_serde")))]
+#[tokio::test]
+async fn traces_are_displayed_as_a_tree() {
+    use std::any::type_name;
+
+    let trace = failing_trace().await;
+    let expected = format!(
+        "\
+OUTER ({unit}) -> u32 [outer] ::: nested call error
+├── DOUBLE (u32) -> u32 [double] ::: success
+└── MIDDLE ({unit}) -> u32 [middle] ::: nested call error
+    └── MISSING ({string}) -> {unit} ::: bus error: No handler matches the event!  <-- source
+",
+        unit = type_name::<()>(),
+        string = type_name::<String>(),
+    );
+    assert_eq!(trace.to_string(), expected);
+    assert_eq!(trace.plain().to_string(), expected);
+    assert_eq!(trace.display(), expected);
+}
+
+#[cfg(feature = "backtrace_track_values")]
+#[tokio::test]
+async fn recorded_values_are_listed_under_their_call() {
+    let trace = failing_trace().await.to_string();
+    let lines = trace.lines().collect::<Vec<_>>();
+    let double = lines
+        .iter()
+        .position(|line| line.contains("DOUBLE"))
+        .unwrap();
+    assert_eq!(lines[double + 1], "│       args: 3");
+    assert_eq!(lines[double + 2], "│       ret: 6");
+    let missing = lines
+        .iter()
+        .position(|line| line.contains("MISSING"))
+        .unwrap();
+    assert_eq!(lines[missing + 1], "            args: \"hello\"");
+}
+
+#[tokio::test]
+async fn colored_traces_only_add_escape_codes() {
+    let trace = failing_trace().await;
+    let colored = trace.colored().to_string();
+    assert_ne!(colored, trace.to_string());
+    assert_eq!(strip_ansi(&colored), trace.to_string());
+
+    let lines = colored.lines().collect::<Vec<_>>();
+    // the failing path is bold
+    for name in ["OUTER", "MIDDLE", "MISSING"] {
+        let line = lines.iter().find(|line| line.contains(name)).unwrap();
+        assert!(line.contains(&format!("\x1b[1m{name} (")), "{line:?}");
+    }
+    let double = lines.iter().find(|line| line.contains("DOUBLE")).unwrap();
+    assert!(!double.contains("\x1b[1m"));
+    assert!(double.contains("\x1b[32msuccess\x1b[0m"));
+    // the source is red
+    let missing = lines.iter().find(|line| line.contains("MISSING")).unwrap();
+    assert!(missing.contains("\x1b[31m  <-- source\x1b[0m"));
+}
+
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip to the end of the `ESC [ ... m` sequence
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use dabus::{event, BusInterface, BusStop, DABus, EventRegister};
+
+event!(READ, (), u32);
+event!(NESTED_READ, (), u32);
+event!(WRITE, u32, ());
+
+#[derive(Debug, Default)]
+struct Store {
+    value: u32,
+}
+
+impl Store {
+    async fn read(&self, (): (), _i: BusInterface) -> u32 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        self.value
+    }
+
+    async fn nested_read(&self, (): (), mut i: BusInterface) -> u32 {
+        // gives `WRITE` time to be queued
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        i.fire(READ, ()).await.unwrap()
+    }
+
+    async fn write(&mut self, value: u32, _i: BusInterface) {
+        self.value = value;
+    }
+}
+
+impl BusStop for Store {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.shared_handler(READ, Self::read)
+            .shared_handler(NESTED_READ, Self::nested_read)
+            .handler(WRITE, Self::write)
+    }
+}
+
+event!(READ_WHILE_WRITING, (), (Vec<u32>, Vec<u32>));
+
+#[derive(Debug)]
+struct Driver;
+
+impl Driver {
+    /// two overlapping streams of reads, so that the store is never free, and a write part way through
+    async fn read_while_writing(&mut self, (): (), i: BusInterface) -> (Vec<u32>, Vec<u32>) {
+        let reads = |mut i: BusInterface, delay| async move {
+            tokio::time::sleep(delay).await;
+            let mut values = vec![];
+            for _ in 0..10 {
+                values.push(i.fire(READ, ()).await.unwrap());
+            }
+            values
+        };
+        let mut writer = i.clone();
+        let write = async move {
+            tokio::time::sleep(Duration::from_millis(15)).await;
+            writer.fire(WRITE, 1).await.unwrap();
+        };
+        let (first, second, ()) = futures::join!(
+            reads(i.clone(), Duration::ZERO),
+            reads(i, Duration::from_millis(5)),
+            write
+        );
+        (first, second)
+    }
+}
+
+impl BusStop for Driver {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(READ_WHILE_WRITING, Self::read_while_writing)
+    }
+}
+
+#[tokio::test]
+async fn queued_writes_are_not_starved_by_reads() {
+    let mut bus = DABus::new();
+    bus.register(Store::default());
+    bus.register(Driver);
+    let (first, second) = bus.fire(READ_WHILE_WRITING, ()).await.unwrap().ret();
+    // the write runs once the reads it was queued behind finish, before the rest of the reads
+    assert_eq!(first.first(), Some(&0));
+    assert_eq!(first.last(), Some(&1));
+    assert_eq!(second.last(), Some(&1));
+}
+
+event!(NESTED_READ_WHILE_WRITING, (), u32);
+
+#[derive(Debug)]
+struct NestedDriver;
+
+impl NestedDriver {
+    async fn nested_read_while_writing(&mut self, (): (), i: BusInterface) -> u32 {
+        let mut reader = i.clone();
+        let mut writer = i;
+        let write = async move {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            writer.fire(WRITE, 1).await.unwrap();
+        };
+        let (value, ()) = futures::join!(reader.fire(NESTED_READ, ()), write);
+        value.unwrap()
+    }
+}
+
+impl BusStop for NestedDriver {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(NESTED_READ_WHILE_WRITING, Self::nested_read_while_writing)
+    }
+}
+
+#[tokio::test]
+async fn shared_calls_from_readers_join_them_while_a_write_is_queued() {
+    let mut bus = DABus::new();
+    bus.register(Store::default());
+    bus.register(NestedDriver);
+    // the nested read can not wait for the write, which waits for the read that made it
+    assert_eq!(
+        bus.fire(NESTED_READ_WHILE_WRITING, ()).await.unwrap().ret(),
+        0
+    );
+    assert_eq!(bus.deregister::<Store>()[0].value, 1);
+}